@@ -0,0 +1,317 @@
+use super::{find_site_files, get_config, minify, relative_path, report_error, write_to_path, FileType};
+use base64::Engine;
+use cached::proc_macro::cached;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha384};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Dependencies of each compiled Sass entry point, used by the watcher to
+/// know which entry points to re-compile when a Sass file changes.
+pub(crate) static SASS_DEPENDENCIES: Mutex<Vec<(String, Vec<String>)>> = Mutex::new(Vec::new());
+
+/// Compiled Sass entry points, see `compile_sass_file`.
+pub(crate) static SASS_OUTPUTS: Mutex<Vec<CompiledSass>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetManifestEntry {
+    pub path: String,
+    pub integrity: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SassOutput {
+    pub css: String,
+    pub dependencies: Vec<String>,
+}
+
+/// A compiled Sass entry point in `path`, along with the modification times
+/// of the files that went into it when it was compiled.
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledSass {
+    path: String,
+    modified: Vec<Option<SystemTime>>,
+    output: SassOutput,
+}
+
+/// A Sass file system that keeps track of the files it reads, which are the
+/// entry point and all the files it imports.
+#[derive(Debug, Default)]
+struct RecordingFs {
+    read: Mutex<Vec<PathBuf>>,
+}
+
+impl grass::Fs for RecordingFs {
+    fn is_dir(&self, path: &Path) -> bool {
+        return path.is_dir();
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        return path.is_file();
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.read.lock().unwrap().push(path.to_path_buf());
+
+        return fs::read(path);
+    }
+}
+
+/// Returns the fingerprinted version of a given asset `path` with a given
+/// content `hash`, such as `/css/style.3f2a9c1b.css` for `/css/style.css`.
+fn fingerprinted_path(path: &str, hash: &str) -> String {
+    let file_name_start = path.rfind('/').map(|i| i + 1).unwrap_or(0);
+
+    return match path.rfind('.').filter(|i| *i > file_name_start) {
+        Some(i) => format!("{}.{}{}", &path[..i], hash, &path[i..]),
+        None => format!("{}.{}", path, hash),
+    };
+}
+
+/// Returns the manifest entry of an asset with given `contents` that is
+/// found in given logical `path`. The entry holds the path to use for the
+/// asset, which is fingerprinted with a hash of the contents when
+/// `FINGERPRINT_ASSETS` is enabled, and the SRI integrity of the asset.
+pub fn asset_manifest_entry(path: &str, contents: &[u8]) -> AssetManifestEntry {
+    let digest = Sha384::digest(contents);
+    let hash: String = digest.iter().take(4).map(|b| format!("{:02x}", b)).collect();
+    let integrity = format!(
+        "sha384-{}",
+        base64::engine::general_purpose::STANDARD.encode(digest)
+    );
+
+    return AssetManifestEntry {
+        path: if get_config().fingerprint_assets {
+            fingerprinted_path(path, &hash)
+        } else {
+            path.to_string()
+        },
+        integrity,
+    };
+}
+
+/// Returns the minified contents of a CSS or JS asset in a given `path`.
+#[cached(time = 2)]
+pub(crate) fn minified_asset_contents(path: String) -> Vec<u8> {
+    let relative_path = relative_path(&path);
+
+    return minify::minify_asset(&relative_path, fs::read(&path).unwrap_or_default());
+}
+
+/// Returns the contents of an asset in a given `path` as they are written to
+/// the output directory, which are minified for CSS and JS assets in
+/// production mode.
+pub fn asset_contents(path: &str) -> Vec<u8> {
+    let relative_path = relative_path(path);
+    let is_minifiable = relative_path.ends_with(".css") || relative_path.ends_with(".js");
+
+    if is_minifiable && minify::should_minify(&relative_path) {
+        return minified_asset_contents(path.to_string());
+    }
+
+    return fs::read(path).unwrap_or_default();
+}
+
+/// Composes the asset manifest which maps the logical path of every
+/// `FileType::Asset` file of the site and its themes, such as
+/// `/css/style.css`, to its manifest entry.
+#[cached(time = 2)]
+pub fn asset_manifest() -> IndexMap<String, AssetManifestEntry> {
+    let mut manifest: IndexMap<String, AssetManifestEntry> = IndexMap::new();
+
+    for asset in find_site_files(FileType::Asset) {
+        let relative_path = relative_path(&asset);
+        let contents = asset_contents(&asset);
+
+        manifest.insert(
+            relative_path.clone(),
+            asset_manifest_entry(&relative_path, &contents),
+        );
+    }
+
+    for entry_point in find_site_files(FileType::Sass) {
+        let relative_path = sass_output_path(&relative_path(&entry_point));
+
+        if let Ok(output) = compile_sass_file(entry_point) {
+            manifest.insert(
+                relative_path.clone(),
+                asset_manifest_entry(&relative_path, output.css.as_bytes()),
+            );
+        }
+    }
+
+    return manifest;
+}
+
+/// Returns the path of the CSS file a Sass/SCSS file in a given `path`
+/// compiles into.
+pub fn sass_output_path(path: &str) -> String {
+    return format!(
+        "{}.css",
+        path.trim_end_matches(".scss").trim_end_matches(".sass")
+    );
+}
+
+/// Returns the modification times of given `files`.
+fn modified_times(files: &[String]) -> Vec<Option<SystemTime>> {
+    return files
+        .iter()
+        .map(|file| fs::metadata(file).and_then(|m| m.modified()).ok())
+        .collect();
+}
+
+/// Compiles a Sass/SCSS entry point in a given `path` into CSS, in the
+/// output style given by `SASS_STYLE` (`expanded` or `compressed`). Returns
+/// the CSS along with the paths of all the files that went into it. The CSS
+/// is compiled again once any of those files has been modified.
+pub fn compile_sass_file(path: String) -> Result<SassOutput, String> {
+    let compiled = SASS_OUTPUTS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|compiled| compiled.path == path)
+        .cloned();
+
+    if let Some(compiled) = compiled {
+        if compiled.modified == modified_times(&compiled.output.dependencies) {
+            return Ok(compiled.output);
+        }
+    }
+
+    let style = if get_config().sass_style == "compressed" {
+        grass::OutputStyle::Compressed
+    } else {
+        grass::OutputStyle::Expanded
+    };
+
+    let recording_fs = RecordingFs::default();
+    let options = grass::Options::default()
+        .style(style)
+        .load_path(&get_config().dir)
+        .fs(&recording_fs);
+    let mut css = grass::from_path(&path, &options).map_err(|e| e.to_string())?;
+    let css_path = sass_output_path(&relative_path(&path));
+
+    if minify::should_minify(&css_path) {
+        css = minify::minify_css(&css)?;
+    }
+
+    let mut dependencies: Vec<String> = recording_fs
+        .read
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect();
+
+    dependencies.sort();
+    dependencies.dedup();
+
+    let output = SassOutput { css, dependencies };
+    let mut compiled = SASS_OUTPUTS.lock().unwrap();
+
+    compiled.retain(|compiled| compiled.path != path);
+    compiled.push(CompiledSass {
+        modified: modified_times(&output.dependencies),
+        output: output.clone(),
+        path,
+    });
+
+    return Ok(output);
+}
+
+/// Compiles Sass/SCSS entry points into CSS files in the output directory,
+/// skipping partials whose name starts with an underscore. When a `changed`
+/// path is given, only entry points that depend on it are compiled. Writes
+/// fingerprinted copies when fingerprinting is enabled. Source maps are not
+/// supported, as Grass doesn't track where in the sources the CSS comes from.
+pub fn compile_sass_assets(changed: Option<&str>) {
+    let config = get_config();
+    let mut sass_dependencies = SASS_DEPENDENCIES.lock().unwrap();
+
+    for entry_point in find_site_files(FileType::Sass) {
+        let is_affected = changed.is_none()
+            || changed == Some(entry_point.as_str())
+            || sass_dependencies.iter().any(|(e, deps)| {
+                e == &entry_point && deps.iter().any(|d| Some(d.as_str()) == changed)
+            });
+
+        if !is_affected {
+            continue;
+        }
+
+        let relative_path = sass_output_path(&relative_path(&entry_point));
+        println!("Compiling {}", relative_path);
+
+        let output = match compile_sass_file(entry_point.clone()) {
+            Ok(output) => output,
+            Err(err) => {
+                report_error(format!(
+                    "Something went wrong within your Sass, {}: {}",
+                    entry_point, err
+                ));
+                continue;
+            }
+        };
+
+        write_to_path(
+            &format!("{}{}", config.output_dir, relative_path),
+            output.css.clone(),
+        );
+
+        if config.fingerprint_assets {
+            let entry = asset_manifest_entry(&relative_path, output.css.as_bytes());
+
+            write_to_path(
+                &format!("{}{}", config.output_dir, entry.path),
+                output.css.clone(),
+            );
+        }
+
+        sass_dependencies.retain(|(e, _)| e != &entry_point);
+        sass_dependencies.push((entry_point, output.dependencies));
+    }
+}
+
+/// Resolves a given logical asset `path` into the path to use for it, which
+/// is the fingerprinted path when fingerprinting is enabled. Paths that are
+/// not in the manifest are returned as they are.
+pub fn resolve_asset_path(path: &str) -> String {
+    let logical_path = format!("/{}", path.trim_start_matches('/'));
+
+    return asset_manifest()
+        .get(&logical_path)
+        .map(|entry| entry.path.clone())
+        .unwrap_or(path.to_string());
+}
+
+#[test]
+fn test_fingerprinted_path() {
+    assert_eq!("/css/style.3f2a9c1b.css", fingerprinted_path("/css/style.css", "3f2a9c1b"));
+    assert_eq!("/js/app.min.3f2a9c1b.js", fingerprinted_path("/js/app.min.js", "3f2a9c1b"));
+    assert_eq!("/.well-known/LICENSE.3f2a9c1b", fingerprinted_path("/.well-known/LICENSE", "3f2a9c1b"));
+}
+
+#[test]
+fn test_compile_sass_file() {
+    let site = super::TestSite::new("sass");
+    site.write("_vars.scss", "$color: red;");
+    site.write("style.scss", "@use \"vars\";\na { b { color: vars.$color; } }");
+
+    let entry_point = site.path("style.scss");
+    let output = compile_sass_file(entry_point.clone()).unwrap();
+    assert!(output.css.contains("a b {\n  color: red;\n}"));
+    assert_eq!(vec![site.path("_vars.scss"), entry_point.clone()], output.dependencies);
+
+    // Saving an imported file re-compiles the entry point right away
+    site.write("_vars.scss", "$color: blue;");
+    let output = compile_sass_file(entry_point).unwrap();
+    assert!(output.css.contains("color: blue;"));
+
+    assert_eq!("/css/style.css", sass_output_path("/css/style.scss"));
+    assert_eq!("/css/style.css", sass_output_path("/css/style.sass"));
+}
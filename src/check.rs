@@ -0,0 +1,348 @@
+use super::get_config;
+use isahc::config::{Configurable, RedirectPolicy};
+use rayon::prelude::*;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckIssue {
+    pub source: String,
+    pub reference: String,
+    pub reason: String,
+}
+
+/// Recursively browses directories within the given `dir` for HTML files.
+fn find_html_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    let read_dir = fs::read_dir(dir);
+
+    if read_dir.is_err() {
+        return files;
+    }
+
+    for entry in read_dir.unwrap().flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            files.extend(find_html_files(&path));
+        } else if path.extension().map(|e| e == "html" || e == "htm").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+
+    return files;
+}
+
+/// Returns all references within given `html`, which are the values of
+/// `href` and `src` attributes as well as the URLs of `srcset` attributes.
+fn find_references(html: &str) -> Vec<String> {
+    let attr_regex = Regex::new(r#"(?i)\s(href|src|srcset)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+    let mut references: Vec<String> = Vec::new();
+
+    for captures in attr_regex.captures_iter(html) {
+        let value = captures
+            .get(2)
+            .or_else(|| captures.get(3))
+            .map(|m| m.as_str())
+            .unwrap_or_default();
+
+        if captures[1].eq_ignore_ascii_case("srcset") {
+            for candidate in value.split(',') {
+                if let Some(url) = candidate.split_whitespace().next() {
+                    references.push(url.to_string());
+                }
+            }
+        } else {
+            references.push(value.trim().to_string());
+        }
+    }
+
+    return references;
+}
+
+/// Returns all fragment identifiers within given `html`, which are the values
+/// of `id` attributes and of `name` attributes of anchors.
+fn find_fragment_ids(html: &str) -> HashSet<String> {
+    let id_regex = Regex::new(r#"(?i)\s(?:id|name)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+
+    return id_regex
+        .captures_iter(html)
+        .filter_map(|c| c.get(1).or_else(|| c.get(2)))
+        .map(|m| m.as_str().to_string())
+        .collect();
+}
+
+/// Determines if the given `reference` is something that can't be checked,
+/// such as an e-mail address, phone number, inline script or data URL.
+fn is_uncheckable(reference: &str) -> bool {
+    let lower = reference.to_lowercase();
+
+    return reference.is_empty()
+        || reference == "#"
+        || lower.starts_with("mailto:")
+        || lower.starts_with("tel:")
+        || lower.starts_with("javascript:")
+        || lower.starts_with("data:")
+        || reference.contains("{{");
+}
+
+/// Determines if the given `reference` points to another site.
+fn is_external(reference: &str) -> bool {
+    let lower = reference.to_lowercase();
+
+    return lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("//");
+}
+
+/// Resolves an internal `path` referenced from a `source` HTML file into the
+/// file it points to within the `public_dir`. Paths to directories resolve to
+/// their `index.html`. Returns `None` if no such file exists.
+fn resolve_internal_path(public_dir: &Path, source: &Path, path: &str) -> Option<PathBuf> {
+    let path = path.replace("%20", " ");
+
+    let target = if path.is_empty() {
+        source.to_path_buf()
+    } else if let Some(absolute) = path.strip_prefix('/') {
+        public_dir.join(absolute)
+    } else {
+        source.parent().unwrap_or(public_dir).join(path)
+    };
+
+    if target.is_file() {
+        return Some(target);
+    }
+
+    if target.is_dir() && target.join("index.html").is_file() {
+        return Some(target.join("index.html"));
+    }
+
+    return None;
+}
+
+/// Checks a given external `url` by requesting it, falling back from a HEAD
+/// to a GET request for servers that don't support HEAD. Returns the reason
+/// why the URL is broken, if it is.
+fn check_external_url(url: &str) -> Option<String> {
+    let url = if url.starts_with("//") {
+        format!("https:{}", url)
+    } else {
+        url.to_string()
+    };
+
+    let client = isahc::HttpClient::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect_policy(RedirectPolicy::Limit(10))
+        .build();
+
+    if client.is_err() {
+        return Some(String::from("could not create HTTP client"));
+    }
+
+    let client = client.unwrap();
+    let mut response = client.head(url.as_str());
+
+    if let Ok(head) = &response {
+        if head.status().as_u16() == 405 || head.status().as_u16() == 501 {
+            response = client.get(url.as_str());
+        }
+    }
+
+    return match response {
+        Ok(response) if response.status().is_client_error() || response.status().is_server_error() => {
+            Some(format!("responded with {}", response.status()))
+        }
+        Ok(_) => None,
+        Err(err) => Some(format!("could not be reached: {}", err)),
+    };
+}
+
+/// Checks all HTML files within given `public_dir` for internal links,
+/// images and other resources that don't exist and for fragments that point
+/// to non-existent IDs. When `external` is enabled, links to other sites are
+/// requested as well. Returns the issues found, ordered by source file.
+pub fn check_public_dir(public_dir: &Path, external: bool) -> Vec<CheckIssue> {
+    let files = find_html_files(public_dir);
+    let contents: HashMap<PathBuf, String> = files
+        .par_iter()
+        .map(|file| (file.clone(), fs::read_to_string(file).unwrap_or_default()))
+        .collect();
+    let fragment_ids: HashMap<PathBuf, HashSet<String>> = contents
+        .par_iter()
+        .map(|(file, html)| (file.clone(), find_fragment_ids(html)))
+        .collect();
+
+    let mut issues: Vec<CheckIssue> = Vec::new();
+    let mut external_references: Vec<(String, String)> = Vec::new();
+
+    for file in &files {
+        let source = file
+            .strip_prefix(public_dir)
+            .map(|p| format!("/{}", p.display()))
+            .unwrap_or(file.display().to_string());
+
+        for reference in find_references(&contents[file]) {
+            if is_uncheckable(&reference) {
+                continue;
+            }
+
+            if is_external(&reference) {
+                external_references.push((source.clone(), reference));
+                continue;
+            }
+
+            let (path, fragment) = match reference.split_once('#') {
+                Some((path, fragment)) => (path, Some(fragment)),
+                None => (reference.as_str(), None),
+            };
+            let path = path.split('?').next().unwrap_or_default();
+
+            let target = match resolve_internal_path(public_dir, file, path) {
+                Some(target) => target,
+                None => {
+                    issues.push(CheckIssue {
+                        source: source.clone(),
+                        reference,
+                        reason: String::from("not found"),
+                    });
+                    continue;
+                }
+            };
+
+            if let (Some(fragment), Some(ids)) = (fragment, fragment_ids.get(&target)) {
+                if !fragment.is_empty() && !ids.contains(fragment) {
+                    issues.push(CheckIssue {
+                        source: source.clone(),
+                        reference,
+                        reason: String::from("anchor not found"),
+                    });
+                }
+            }
+        }
+    }
+
+    if external {
+        let urls: HashSet<String> = external_references.iter().map(|(_, url)| url.clone()).collect();
+        let broken: HashMap<String, String> = urls
+            .into_par_iter()
+            .filter_map(|url| check_external_url(&url).map(|reason| (url, reason)))
+            .collect();
+
+        for (source, reference) in external_references {
+            if let Some(reason) = broken.get(&reference) {
+                issues.push(CheckIssue {
+                    source,
+                    reference,
+                    reason: reason.clone(),
+                });
+            }
+        }
+    }
+
+    issues.sort_by(|a, b| a.source.cmp(&b.source));
+
+    return issues;
+}
+
+/// Checks the output directory for broken links and missing resources and
+/// prints a report grouped by source file. Returns the issues found.
+pub fn check(external: bool) -> Vec<CheckIssue> {
+    println!("Checking ...");
+
+    let public_dir = PathBuf::from(&get_config().output_dir);
+    let issues = check_public_dir(&public_dir, external);
+
+    if issues.is_empty() {
+        println!("No broken links found.");
+        return issues;
+    }
+
+    let mut grouped: BTreeMap<String, Vec<CheckIssue>> = BTreeMap::new();
+
+    for issue in &issues {
+        grouped.entry(issue.source.clone()).or_default().push(issue.clone());
+    }
+
+    for (source, source_issues) in grouped {
+        println!("{}", source);
+
+        for issue in source_issues {
+            println!("  {} ({})", issue.reference, issue.reason);
+        }
+    }
+
+    println!("Found {} broken link(s).", issues.len());
+
+    return issues;
+}
+
+#[test]
+fn test_check_public_dir() {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Local stand-in for external sites
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut stream = stream;
+            let mut buffer = [0; 1024];
+            let read = stream.read(&mut buffer).unwrap_or(0);
+            let request = String::from_utf8_lossy(&buffer[..read]);
+            let status = if request.contains(" /ok ") {
+                "200 OK"
+            } else {
+                "404 Not Found"
+            };
+
+            let _ = stream.write_all(
+                format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+                    .as_bytes(),
+            );
+        }
+    });
+
+    let site = super::TestSite::new("check");
+    site.write("images/cat.png", "");
+    site.write(
+        "blog/post/index.html",
+        "<h2 id=\"intro\">Intro</h2><a href=\"#intro\">Intro</a><a href=\"#outro\">Outro</a>",
+    );
+    site.write(
+        "index.html",
+        format!(
+            "<a href=\"/blog/post\">Post</a><a href=\"/blog/post/#intro\">Intro</a>\
+             <a href=\"/blog/gone\">Gone</a><img src=\"images/cat.png\">\
+             <img srcset=\"/images/cat.png 1x, /images/dog.png 2x\">\
+             <a href=\"mailto:hi@example.com\">Mail</a>\
+             <a href=\"http://127.0.0.1:{0}/ok\">Ok</a><a href=\"http://127.0.0.1:{0}/dead\">Dead</a>",
+            port
+        ),
+    );
+
+    let public_dir = PathBuf::from(&site.dir);
+    let issues = check_public_dir(&public_dir, false);
+    let references: Vec<(&str, &str)> = issues
+        .iter()
+        .map(|i| (i.source.as_str(), i.reference.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("/blog/post/index.html", "#outro"),
+            ("/index.html", "/blog/gone"),
+            ("/index.html", "/images/dog.png"),
+        ],
+        references
+    );
+
+    let issues = check_public_dir(&public_dir, true);
+    assert_eq!(4, issues.len());
+    assert!(issues
+        .iter()
+        .any(|i| i.reference == format!("http://127.0.0.1:{}/dead", port)));
+}
@@ -1,22 +1,91 @@
+use super::utils::SortKey;
 use super::{find_files, get_config, parse_content_files, ContentItem, FileType};
 use cached::proc_macro::cached;
 use indexmap::IndexMap;
 use isahc::prelude::*;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContentDSLItem {
     pub name: String,
     pub from: String,
-    pub sort_by: Option<String>,
-    pub group_by: Option<String>,
+    pub sort_by: Option<ContentDSLSortBy>,
+    pub group_by: Option<ContentDSLGroupBy>,
     pub group_by_order: Option<String>,
     pub group_by_limit: Option<usize>,
+    pub group_limit: Option<usize>,
     pub order: Option<String>,
+    pub offset: Option<usize>,
+    pub slice: Option<(usize, usize)>,
     pub limit: Option<usize>,
     pub headers: Option<HashMap<String, String>>,
+    pub pages: Option<ContentDSLPages>,
+    pub navigation: Option<bool>,
+    pub related: Option<ContentDSLRelated>,
+    pub lang: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ContentDSLSortBy {
+    Single(String),
+    Multiple(Vec<ContentDSLSortKey>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ContentDSLSortKey {
+    Simple(String),
+    Detailed {
+        by: String,
+        order: Option<String>,
+        #[serde(rename = "type")]
+        compare: Option<String>,
+        missing: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ContentDSLGroupBy {
+    Single(String),
+    Multiple(Vec<ContentDSLGrouper>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ContentDSLGrouper {
+    Simple(String),
+    Detailed {
+        by: String,
+        order: Option<String>,
+        limit: Option<usize>,
+        format: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentDSLPages {
+    pub layout: String,
+    pub slug: String,
+    pub records: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentDSLRelated {
+    pub by: ContentDSLRelatedBy,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ContentDSLRelatedBy {
+    Keys(Vec<String>),
+    Weighted(IndexMap<String, f64>),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,91 +94,211 @@ pub enum TemplateContentDSLItem {
     Normal(Vec<ContentItem>),
     Grouped(IndexMap<String, Vec<ContentItem>>),
     Single(ContentItem),
+    Nested(Vec<ContentGroup>),
     Pulled(serde_json::Value),
 }
 
-/// Sort, order and limit given `items` according to given `dsl`.
-fn dsl_sort_order_limit(dsl: ContentDSLItem, items: &mut Vec<ContentItem>) -> Vec<ContentItem> {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentGroup {
+    pub key: String,
+    pub label: String,
+    pub count: usize,
+    pub items: Vec<ContentItem>,
+    pub groups: Vec<ContentGroup>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ContentNavigation {
+    pub prev: Option<ContentItem>,
+    pub next: Option<ContentItem>,
+    pub related: Vec<ContentItem>,
+}
+
+/// Resolves the sort keys of given `sort_by`, falling back to given `order`
+/// for keys that don't have an order of their own.
+fn dsl_sort_keys(sort_by: ContentDSLSortBy, order: Option<String>) -> Vec<SortKey> {
+    let default_order = order.unwrap_or(String::from("desc"));
+    let keys = match sort_by {
+        ContentDSLSortBy::Single(by) => vec![ContentDSLSortKey::Simple(by)],
+        ContentDSLSortBy::Multiple(keys) => keys,
+    };
+
+    return keys
+        .into_iter()
+        .map(|key| match key {
+            ContentDSLSortKey::Simple(by) => SortKey {
+                by,
+                order: default_order.clone(),
+                compare: String::from("auto"),
+                missing: String::from("last"),
+            },
+            ContentDSLSortKey::Detailed {
+                by,
+                order,
+                compare,
+                missing,
+            } => SortKey {
+                by,
+                order: order.unwrap_or(default_order.clone()),
+                compare: compare.unwrap_or(String::from("auto")),
+                missing: missing.unwrap_or(String::from("last")),
+            },
+        })
+        .collect();
+}
+
+/// Sort, order, slice, offset and limit given `items` according to given
+/// `dsl`. The `slice` is a `[start, end]` range of the sorted items, and
+/// `offset` and `limit` are applied after it.
+fn dsl_sort_order_limit(dsl: ContentDSLItem, items: &mut [ContentItem]) -> Vec<ContentItem> {
     // Sort and order?
-    if dsl.sort_by.is_some() {
-        super::utils::sort_content_items(
-            items,
-            dsl.sort_by.unwrap_or(String::from("slug")),
-            dsl.order.unwrap_or(String::from("desc")),
-        );
+    if let Some(sort_by) = dsl.sort_by {
+        super::utils::sort_content_items(items, &dsl_sort_keys(sort_by, dsl.order));
+    }
+
+    let mut items = items.to_vec();
+
+    // Slice?
+    if let Some((start, end)) = dsl.slice {
+        let end = end.min(items.len());
+        let start = start.min(end);
+
+        items = items[start..end].to_vec();
+    }
+
+    // Offset?
+    if let Some(offset) = dsl.offset {
+        items.drain(..offset.min(items.len()));
     }
 
     // Limit?
-    if dsl.limit.is_some() {
-        items.truncate(dsl.limit.unwrap());
+    if let Some(limit) = dsl.limit {
+        items.truncate(limit);
     }
 
-    return items.to_vec();
+    return items;
 }
 
 /// Returns a grouper from a given `item` according to given `by`. The
 /// `by` can be any top-level struct key as well as meta-level key, such as
-/// `meta.date`. In the case of `meta.date`, it also supports an additional
-/// modifier such as `meta.date|year`, to group by year. `month` and `day`
-/// are also supported.
-fn dsl_group_by_grouper(item: &ContentItem, by: &String) -> String {
+/// `meta.date`. Meta keys holding a date also support an additional modifier,
+/// such as `meta.date|year`, to group by year. `month`, `day`, `week`,
+/// `quarter`, `weekday` (1 for Monday through 7 for Sunday), `year-month` and
+/// custom date formats like `%Y/%m` are also supported.
+fn dsl_group_by_grouper(item: &ContentItem, by: &str) -> String {
     let grouper: String;
 
     // Meta-key grouping.
     if by.contains("meta.") {
-        let meta_key: String;
+        // Construct key and modifier
+        let (meta_key, meta_modifier) = dsl_grouper_meta_key(by);
 
-        // Construct key
-        if by.contains("|") {
-            let whole_key = by.replace("meta.", "");
-            let meta_key_split: Vec<&str> = whole_key.split("|").collect();
-            meta_key = meta_key_split[0].to_string();
+        // Construct value
+        let value = item.meta.get(&meta_key).cloned().unwrap_or_default();
+
+        // If we have a modifier, the value is a date to group by a part of
+        if !meta_modifier.is_empty() {
+            grouper = dsl_date_grouper(&value, &meta_modifier);
+            // Otherwise, the value itself is the grouper
         } else {
-            meta_key = by.replace("meta.", "");
+            grouper = value;
         }
+        // Group by top-level field key.
+    } else {
+        grouper = super::utils::get_field_by_name(item, by);
+    }
 
-        // Construct modifier
-        let meta_modifier: String;
+    return grouper;
+}
 
-        if by.contains("|") {
-            let whole_key = by.replace("meta.", "");
-            let meta_key_split: Vec<&str> = whole_key.split("|").collect();
-            meta_modifier = meta_key_split[1].to_string();
-        } else {
-            meta_modifier = String::new();
+/// Matches partial dates that only hold a year or a year and month, such as
+/// `2021` or `2021-03`.
+static PARTIAL_DATE_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// Returns a grouper from a given date `value` according to given
+/// `modifier`. The date is parsed as an ISO date, a date-time with an offset,
+/// an RFC 2822 date-time or one of the other formats `parse_date` supports,
+/// and then moved into the site's timezone. Values that only hold a
+/// year or a year and month, such as `2021` or `2021-03`, can still be
+/// grouped by those. Values that can't be parsed result in an empty grouper.
+fn dsl_date_grouper(value: &str, modifier: &str) -> String {
+    let timezone = super::utils::site_timezone();
+    let date = super::utils::parse_date(value);
+    let format = |format: &str| -> String {
+        return date
+            .and_then(|date| super::utils::format_date(&date, format, timezone, None).ok())
+            .unwrap_or_default();
+    };
+
+    if date.is_some() {
+        return match modifier {
+            "year" => format("%Y"),
+            "month" => format("%m"),
+            "day" => format("%d"),
+            "week" => format("%G-W%V"),
+            "quarter" => format!("Q{}", (format("%m").parse::<u32>().unwrap_or(1) - 1) / 3 + 1),
+            "weekday" => format("%u"),
+            "year-month" => format("%Y-%m"),
+            spec if spec.contains('%') => format(spec),
+            _ => String::new(),
         };
+    }
 
-        // Construct value
-        let value;
+    // Partial dates, such as `2021` or `2021-03`
+    let regex = PARTIAL_DATE_REGEX.get_or_init(|| Regex::new(r"^(\d{4})(?:-(\d{2}))?$").unwrap());
 
-        if item.meta.get(&meta_key).is_some() {
-            value = item.meta.get(&meta_key).unwrap().to_string();
-        } else {
-            value = String::new();
+    if let Some(captures) = regex.captures(value.trim()) {
+        let year = captures.get(1).map(|m| m.as_str());
+        let month = captures.get(2).map(|m| m.as_str());
+
+        return match (modifier, year, month) {
+            ("year", Some(year), _) => year.to_string(),
+            ("month", _, Some(month)) => month.to_string(),
+            ("year-month", Some(year), Some(month)) => format!("{}-{}", year, month),
+            _ => String::new(),
         };
+    }
 
-        // If we're grouping by meta.date and have `year` as a modifier
-        if meta_key == "date" && meta_modifier == "year" {
-            let date_parts: Vec<&str> = value.split("-").collect();
-            grouper = date_parts[0].to_string();
-            // If we're grouping by meta.date and have `month` as a modifier
-        } else if meta_key == "date" && meta_modifier == "month" {
-            let date_parts: Vec<&str> = value.split("-").collect();
-            grouper = date_parts[1].to_string();
-            // If we're grouping by meta.date and have `day` as a modifier
-        } else if meta_key == "date" && meta_modifier == "day" {
-            let date_parts: Vec<&str> = value.split("-").collect();
-            grouper = date_parts[2].to_string();
-            // Otherwise, the value itself is the grouper
-        } else {
-            grouper = value;
+    return String::new();
+}
+
+/// Splits given meta-level grouper `by`, such as `meta.date|year`, into the
+/// meta key and the (possibly empty) modifier.
+fn dsl_grouper_meta_key(by: &str) -> (String, String) {
+    let whole_key = by.replace("meta.", "");
+    let meta_key_split: Vec<&str> = whole_key.split('|').collect();
+
+    return (
+        meta_key_split[0].to_string(),
+        meta_key_split.get(1).copied().unwrap_or_default().to_string(),
+    );
+}
+
+/// Returns the label of a group with given `key` and `items` that was grouped
+/// by given `by`. When a date `format` is given and the grouper is a meta
+/// key holding a date, the label is the date of the first item formatted
+/// with it in the site's timezone and locale, such as `March` for `%B`.
+/// Otherwise, or when the format is invalid, the label is the key itself.
+fn dsl_group_label(key: &str, items: &[ContentItem], by: &str, format: Option<String>) -> String {
+    if let (Some(format), true) = (format, by.contains("meta.")) {
+        let (meta_key, _) = dsl_grouper_meta_key(by);
+        let date = items
+            .first()
+            .and_then(|item| item.meta.get(&meta_key))
+            .and_then(|value| super::utils::parse_date(value));
+
+        if let Some(date) = date {
+            let timezone = super::utils::site_timezone();
+            let locale = get_config().locale.clone();
+
+            match super::utils::format_date(&date, &format, timezone, locale.as_deref()) {
+                Ok(label) => return label,
+                Err(err) => println!("Could not format the label of group {}: {}", key, err),
+            }
         }
-        // Group by top-level field key.
-    } else {
-        grouper = super::utils::get_field_by_name(item, &by);
     }
 
-    return grouper;
+    return key.to_string();
 }
 
 /// Order given `groups` in either a descending or ascending order. Given
@@ -127,15 +316,16 @@ fn dsl_group_order_limit(
     }
 
     // Order
-    keys.sort();
+    let compare = super::utils::column_compare(&keys, "auto");
+    keys.sort_by(|a, b| super::utils::compare_values(a, b, &compare));
 
     if order == "desc" {
         keys.reverse();
     }
 
     // Limit
-    if limit.is_some() {
-        keys.truncate(limit.unwrap());
+    if let Some(limit) = limit {
+        keys.truncate(limit);
     }
 
     // Construct IndexMap
@@ -148,12 +338,14 @@ fn dsl_group_order_limit(
 }
 
 /// Group given `items` by given `by` and, optionally, order the groups by
-/// given `order`.
+/// given `order`, limit the number of groups by given `limit` and limit the
+/// number of items within each group by given `items_limit`.
 fn dsl_group(
     items: Vec<ContentItem>,
     by: String,
     order: Option<String>,
     limit: Option<usize>,
+    items_limit: Option<usize>,
 ) -> IndexMap<String, Vec<ContentItem>> {
     // If by is not provided, return nothing. This is so that the
     // `compose_content_from_dsl` function would know which enum
@@ -184,13 +376,86 @@ fn dsl_group(
     }
 
     // Order the groups by either descending (default) or ascending order.
-    if order.is_some() {
-        grouped_content = dsl_group_order_limit(grouped_content, order.unwrap(), limit);
+    if let Some(order) = order {
+        grouped_content = dsl_group_order_limit(grouped_content, order, limit);
+    }
+
+    // Limit the items within each group.
+    if let Some(items_limit) = items_limit {
+        for group_items in grouped_content.values_mut() {
+            group_items.truncate(items_limit);
+        }
     }
 
     return grouped_content;
 }
 
+/// Group given `items` by the first of given `groupers` and then each group
+/// recursively by the rest of them, resulting in nested groups that carry
+/// their key, label and item count. Groupers that don't have an order or
+/// limit of their own fall back to given `order` and `limit`. The items of
+/// the innermost groups are limited by given `items_limit`.
+fn dsl_nested_group(
+    items: Vec<ContentItem>,
+    groupers: &[ContentDSLGrouper],
+    order: Option<String>,
+    limit: Option<usize>,
+    items_limit: Option<usize>,
+) -> Vec<ContentGroup> {
+    let (grouper, rest) = match groupers.split_first() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+
+    let (by, grouper_order, grouper_limit, format) = match grouper.clone() {
+        ContentDSLGrouper::Simple(by) => (by, None, None, None),
+        ContentDSLGrouper::Detailed {
+            by,
+            order,
+            limit,
+            format,
+        } => (by, order, limit, format),
+    };
+
+    let groups = dsl_group(
+        items,
+        by.clone(),
+        grouper_order.or(order.clone()),
+        grouper_limit.or(limit),
+        None,
+    );
+
+    return groups
+        .into_iter()
+        .map(|(key, mut group_items)| {
+            let label = dsl_group_label(&key, &group_items, &by, format.clone());
+            let count = group_items.len();
+            let groups = dsl_nested_group(
+                group_items.clone(),
+                rest,
+                order.clone(),
+                limit,
+                items_limit,
+            );
+
+            // Only the innermost groups carry their items.
+            if !rest.is_empty() {
+                group_items.clear();
+            } else if let Some(items_limit) = items_limit {
+                group_items.truncate(items_limit);
+            }
+
+            return ContentGroup {
+                key,
+                label,
+                count,
+                items: group_items,
+                groups,
+            };
+        })
+        .collect();
+}
+
 /// Fetches content from a URL that's given as `from` and optionally
 /// passes along `headers` to that request (for authentication purposes,
 /// for example).
@@ -199,7 +464,7 @@ fn get_content_from_http(
     headers: Option<HashMap<String, String>>,
 ) -> Option<TemplateContentDSLItem> {
     let client = isahc::HttpClient::builder()
-        .default_headers(headers.unwrap_or(HashMap::new()))
+        .default_headers(headers.unwrap_or_default())
         .build();
 
     if client.is_err() {
@@ -208,14 +473,75 @@ fn get_content_from_http(
 
     let response = client.unwrap().get(from);
 
-    if response.is_ok() {
-        return Some(TemplateContentDSLItem::Pulled(
-            serde_json::from_str(&response.unwrap().text().unwrap()).unwrap(),
-        ));
+    match response {
+        Ok(mut response) => {
+            return Some(TemplateContentDSLItem::Pulled(
+                serde_json::from_str(&response.text().unwrap()).unwrap(),
+            ));
+        }
+        Err(err) => {
+            println!("{:#?}", err);
+            return None;
+        }
+    }
+}
+
+/// Determines if the given DSL `from` points to a local data file.
+pub fn is_data_source(from: &str) -> bool {
+    return !from.starts_with("http")
+        && (from.ends_with(".json") || from.ends_with(".yaml") || from.ends_with(".yml"));
+}
+
+/// Reads content from a local JSON or YAML data file that's given as `from`,
+/// relative to the given `dir`.
+fn get_content_from_data_file(from: String, dir: String) -> Option<TemplateContentDSLItem> {
+    let path_str = format!("{}{}{}", dir, "/", from);
+    let contents = fs::read_to_string(&path_str);
+
+    if contents.is_err() {
+        println!("Could not read data file {}", path_str);
+        return None;
+    }
+
+    let value: Result<serde_json::Value, String> = if from.ends_with(".json") {
+        serde_json::from_str(&contents.unwrap()).map_err(|e| e.to_string())
+    } else {
+        serde_yaml::from_str(&contents.unwrap()).map_err(|e| e.to_string())
+    };
+
+    match value {
+        Ok(value) => return Some(TemplateContentDSLItem::Pulled(value)),
+        Err(err) => {
+            println!("Could not parse data file {}: {}", path_str, err);
+            return None;
+        }
+    }
+}
+
+/// Returns the records of a pulled `value` that should each become a page.
+/// The `records` is a dot-separated path to an array within the value, such
+/// as `data.products`. When not given, the value itself is expected to be
+/// the array of records.
+pub fn get_records(value: &serde_json::Value, records: Option<String>) -> Vec<serde_json::Value> {
+    let mut target = value;
+
+    for key in records.unwrap_or_default().split('.').filter(|k| !k.is_empty()) {
+        let next = match target {
+            serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => target.get(key),
+        };
+
+        match next {
+            Some(next) => target = next,
+            None => return Vec::new(),
+        }
     }
 
-    println!("{:#?}", response.err());
-    return None;
+    return match target {
+        serde_json::Value::Array(items) => items.to_vec(),
+        serde_json::Value::Null => Vec::new(),
+        _ => vec![target.clone()],
+    };
 }
 
 /// Gets content from disk, which can be a singular Markdown file,
@@ -233,18 +559,34 @@ fn get_content_from_disk(item: ContentDSLItem, dir: String) -> Option<TemplateCo
 
     let mut parsed_content_files = parse_content_files(content_files);
 
-    if single_item && parsed_content_files.len() > 0 {
+    // Language-scoped datasets only hold content in their language
+    if let Some(lang) = &item.lang {
+        parsed_content_files.retain(|content_item| &content_item.lang == lang);
+    }
+
+    if single_item && !parsed_content_files.is_empty() {
         return Some(TemplateContentDSLItem::Single(
             parsed_content_files.first().unwrap().clone(),
         ));
     }
 
-    if item.group_by.is_some() {
+    if let Some(ContentDSLGroupBy::Single(group_by)) = item.group_by.clone() {
         return Some(TemplateContentDSLItem::Grouped(dsl_group(
             dsl_sort_order_limit(item.clone(), &mut parsed_content_files),
-            item.group_by.unwrap(),
+            group_by,
+            item.group_by_order,
+            item.group_by_limit,
+            item.group_limit,
+        )));
+    }
+
+    if let Some(ContentDSLGroupBy::Multiple(groupers)) = item.group_by.clone() {
+        return Some(TemplateContentDSLItem::Nested(dsl_nested_group(
+            dsl_sort_order_limit(item.clone(), &mut parsed_content_files),
+            &groupers,
             item.group_by_order,
             item.group_by_limit,
+            item.group_limit,
         )));
     }
 
@@ -254,40 +596,346 @@ fn get_content_from_disk(item: ContentDSLItem, dir: String) -> Option<TemplateCo
     )));
 }
 
-/// Composes content data from the `content.json` DSL which allows users to
-/// create data-sets from the available content files, further enabling more
-/// dynamic-ish site creation.
+/// Returns the items of the `content.json` DSL.
 #[cached(time = 2)]
-pub fn compose_content_from_dsl() -> HashMap<String, TemplateContentDSLItem> {
+pub fn get_dsl_items() -> Vec<ContentDSLItem> {
     let config = get_config();
     let file_contents = fs::read_to_string(format!("{}{}", config.dir, "/content.json"));
     let contents = file_contents.unwrap_or_default();
-    let dsl: Result<Vec<ContentDSLItem>, serde_json::Error> = serde_json::from_str(&contents);
 
-    if dsl.is_err() {
-        return HashMap::new();
-    }
+    return serde_json::from_str(&contents).unwrap_or_default();
+}
 
+/// Composes content data from the `content.json` DSL which allows users to
+/// create data-sets from the available content files, further enabling more
+/// dynamic-ish site creation.
+#[cached(time = 2)]
+pub fn compose_content_from_dsl() -> HashMap<String, TemplateContentDSLItem> {
+    let config = get_config();
     let mut content: HashMap<String, TemplateContentDSLItem> = HashMap::new();
 
-    for dsl_item in dsl.unwrap_or(Vec::new()) {
+    for dsl_item in get_dsl_items() {
         // HTTP fetched data
         if dsl_item.from.starts_with("http") {
             let http_content = get_content_from_http(dsl_item.from, dsl_item.headers);
 
-            if http_content.is_some() {
-                content.insert(dsl_item.name, http_content.unwrap());
+            if let Some(http_content) = http_content {
+                content.insert(dsl_item.name, http_content);
+            }
+        }
+        // Local data file data
+        else if is_data_source(&dsl_item.from) {
+            let data_content = get_content_from_data_file(dsl_item.from, config.dir.clone());
+
+            if let Some(data_content) = data_content {
+                content.insert(dsl_item.name, data_content);
             }
         }
         // Markdown data
         else {
             let disk_content = get_content_from_disk(dsl_item.clone(), config.dir.clone());
 
-            if disk_content.is_some() {
-                content.insert(dsl_item.name, disk_content.unwrap());
+            if let Some(disk_content) = disk_content {
+                content.insert(dsl_item.name, disk_content);
             }
         }
     }
 
     return content;
 }
+
+/// Returns the items of a given dataset `content` in the order they appear
+/// in, flattening grouped and nested datasets.
+fn dsl_dataset_items(content: &TemplateContentDSLItem) -> Vec<ContentItem> {
+    fn nested_items(groups: &[ContentGroup]) -> Vec<ContentItem> {
+        return groups
+            .iter()
+            .flat_map(|group| {
+                let mut items = group.items.clone();
+                items.extend(nested_items(&group.groups));
+                return items;
+            })
+            .collect();
+    }
+
+    return match content {
+        TemplateContentDSLItem::Normal(items) => items.clone(),
+        TemplateContentDSLItem::Grouped(groups) => groups.values().flatten().cloned().collect(),
+        TemplateContentDSLItem::Single(item) => vec![item.clone()],
+        TemplateContentDSLItem::Nested(groups) => nested_items(groups),
+        TemplateContentDSLItem::Pulled(_) => Vec::new(),
+    };
+}
+
+/// Returns the taxonomy terms of a given `item` for a given meta `key`, such
+/// as `meta.tags`. Terms are comma-separated and compared case-insensitively.
+fn dsl_related_terms(item: &ContentItem, key: &str) -> Vec<String> {
+    let meta_key = key.strip_prefix("meta.").unwrap_or(key);
+    let value = item.meta.get(meta_key).cloned().unwrap_or_default();
+
+    return value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|term| term.trim().trim_matches('"').trim_matches('\'').to_lowercase())
+        .filter(|term| !term.is_empty())
+        .collect();
+}
+
+/// Returns up to `limit` items from given `items` that are related to given
+/// `item`, scored by the number of shared terms in each of the meta keys of
+/// given `by`, multiplied by the weight of that key. Items that share nothing
+/// aren't related at all.
+fn dsl_related_items(
+    item: &ContentItem,
+    items: &[ContentItem],
+    by: &ContentDSLRelatedBy,
+    limit: usize,
+) -> Vec<ContentItem> {
+    let weights: Vec<(String, f64)> = match by {
+        ContentDSLRelatedBy::Keys(keys) => keys.iter().map(|key| (key.clone(), 1.0)).collect(),
+        ContentDSLRelatedBy::Weighted(weights) => {
+            weights.iter().map(|(key, weight)| (key.clone(), *weight)).collect()
+        }
+    };
+
+    let mut scored: Vec<(f64, &ContentItem)> = items
+        .iter()
+        .filter(|other| other.path != item.path)
+        .map(|other| {
+            let score = weights
+                .iter()
+                .map(|(key, weight)| {
+                    let terms = dsl_related_terms(item, key);
+                    let shared = dsl_related_terms(other, key)
+                        .iter()
+                        .filter(|term| terms.contains(term))
+                        .count();
+
+                    return shared as f64 * weight;
+                })
+                .sum();
+
+            return (score, other);
+        })
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    return scored
+        .into_iter()
+        .take(limit)
+        .map(|(_, other)| other.clone())
+        .collect();
+}
+
+/// Composes the navigation of content items from the `content.json` DSL.
+/// Each item of a dataset with `navigation` gets the items before and after
+/// it in that dataset as `prev` and `next`, and each item of a dataset with
+/// `related` gets the items of that dataset related to it. Returns the
+/// navigation by content item path. When an item is in several such
+/// datasets, the first one wins.
+#[cached(time = 2)]
+pub fn compose_content_navigation() -> HashMap<String, ContentNavigation> {
+    let content = compose_content_from_dsl();
+    let mut navigation: HashMap<String, ContentNavigation> = HashMap::new();
+    let mut with_prev_next: HashSet<String> = HashSet::new();
+    let mut with_related: HashSet<String> = HashSet::new();
+
+    for dsl_item in get_dsl_items() {
+        let items = match content.get(&dsl_item.name) {
+            Some(dataset) => dsl_dataset_items(dataset),
+            None => continue,
+        };
+
+        for (index, item) in items.iter().enumerate() {
+            let item_navigation = navigation.entry(item.path.clone()).or_default();
+
+            if dsl_item.navigation.unwrap_or(false) && with_prev_next.insert(item.path.clone()) {
+                item_navigation.prev = index.checked_sub(1).and_then(|i| items.get(i)).cloned();
+                item_navigation.next = items.get(index + 1).cloned();
+            }
+
+            if let (Some(related), true) = (&dsl_item.related, !with_related.contains(&item.path)) {
+                with_related.insert(item.path.clone());
+                item_navigation.related =
+                    dsl_related_items(item, &items, &related.by, related.limit.unwrap_or(5));
+            }
+        }
+    }
+
+    return navigation;
+}
+
+#[test]
+fn test_get_records() {
+    let value: serde_json::Value =
+        serde_json::from_str(r#"{"data": {"products": [{"id": 1}, {"id": 2}]}}"#).unwrap();
+
+    let records = get_records(&value, Some("data.products".to_string()));
+    assert_eq!(2, records.len());
+    assert_eq!(serde_json::json!({"id": 2}), records[1]);
+
+    let records = get_records(&value, Some("data.products.0".to_string()));
+    assert_eq!(vec![serde_json::json!({"id": 1})], records);
+
+    let records = get_records(&value, Some("data.doesnt_exist".to_string()));
+    assert_eq!(0, records.len());
+
+    let records = get_records(&value, None);
+    assert_eq!(vec![value.clone()], records);
+}
+
+#[test]
+fn test_dsl_sort_order_limit() {
+    let items: Vec<ContentItem> = (1..=8)
+        .map(|i| ContentItem {
+            path: format!("post-{}", i),
+            slug: format!("/post-{}", i),
+            meta: Default::default(),
+            entry: String::new(),
+            time_to_read: 0,
+            ..Default::default()
+        })
+        .collect();
+
+    let slugs = |dsl: &str| -> Vec<String> {
+        let dsl: ContentDSLItem = serde_json::from_str(dsl).unwrap();
+
+        return dsl_sort_order_limit(dsl, &mut items.clone())
+            .iter()
+            .map(|item| item.slug.clone())
+            .collect();
+    };
+
+    let hero = slugs(r#"{"name": "hero", "from": "blog", "sort_by": "slug", "limit": 1}"#);
+    assert_eq!(vec!["/post-8"], hero);
+
+    let grid = slugs(r#"{"name": "grid", "from": "blog", "sort_by": "slug", "offset": 1, "limit": 5}"#);
+    assert_eq!(vec!["/post-7", "/post-6", "/post-5", "/post-4", "/post-3"], grid);
+
+    let sliced = slugs(r#"{"name": "sliced", "from": "blog", "sort_by": "slug", "slice": [2, 4]}"#);
+    assert_eq!(vec!["/post-6", "/post-5"], sliced);
+
+    let out_of_bounds = slugs(r#"{"name": "oob", "from": "blog", "offset": 10, "slice": [6, 20]}"#);
+    assert_eq!(0, out_of_bounds.len());
+}
+
+#[test]
+fn test_dsl_nested_group() {
+    let _config = super::lock_config(super::test_config("/site"));
+    let items: Vec<ContentItem> = ["2021-03-05", "2022-01-10", "2022-01-20", "2022-02-01"]
+        .iter()
+        .map(|date| ContentItem {
+            path: date.to_string(),
+            slug: format!("/{}", date),
+            meta: [("date".to_string(), date.to_string())].into_iter().collect(),
+            entry: String::new(),
+            time_to_read: 0,
+            ..Default::default()
+        })
+        .collect();
+
+    let groupers: Vec<ContentDSLGrouper> = serde_json::from_str(
+        r#"["meta.date|year", {"by": "meta.date|month", "format": "%B", "limit": 1}]"#,
+    )
+    .unwrap();
+
+    let groups = dsl_nested_group(items, &groupers, Some("desc".to_string()), None, Some(1));
+    assert_eq!(2, groups.len());
+    assert_eq!("2022", groups[0].key);
+    assert_eq!(3, groups[0].count);
+    assert_eq!(0, groups[0].items.len());
+    assert_eq!(1, groups[0].groups.len());
+    assert_eq!("02", groups[0].groups[0].key);
+    assert_eq!("February", groups[0].groups[0].label);
+    assert_eq!(1, groups[0].groups[0].items.len());
+    assert_eq!("2021", groups[1].key);
+    assert_eq!("March", groups[1].groups[0].label);
+}
+
+#[test]
+fn test_dsl_group_label() {
+    let mut config = super::test_config("/site");
+    config.timezone = String::from("-05:00");
+    let _config = super::lock_config(config);
+    let items = vec![ContentItem {
+        meta: [("date".to_string(), "2022-01-01T02:00:00Z".to_string())].into_iter().collect(),
+        ..Default::default()
+    }];
+
+    assert_eq!("December", dsl_group_label("12", &items, "meta.date|month", Some("%B".to_string())));
+    assert_eq!("01", dsl_group_label("01", &items, "meta.date|month", Some("%Q".to_string())));
+    assert_eq!("01", dsl_group_label("01", &items, "meta.date|month", None));
+}
+
+#[test]
+fn test_dsl_date_grouper() {
+    let _config = super::lock_config(super::test_config("/site"));
+    assert_eq!("2021", dsl_date_grouper("2021", "year"));
+    assert_eq!("", dsl_date_grouper("2021", "month"));
+    assert_eq!("2021-03", dsl_date_grouper("2021-03", "year-month"));
+    assert_eq!("03", dsl_date_grouper("2021-03-05", "month"));
+    assert_eq!("06", dsl_date_grouper("2021-03-05T23:30:00-02:00", "day"));
+    assert_eq!("2021-W09", dsl_date_grouper("2021-03-05", "week"));
+    assert_eq!("Q1", dsl_date_grouper("Fri, 05 Mar 2021 10:00:00 +0000", "quarter"));
+    assert_eq!("5", dsl_date_grouper("2021-03-05", "weekday"));
+    assert_eq!("2021/03", dsl_date_grouper("2021-03-05", "%Y/%m"));
+    assert_eq!("", dsl_date_grouper("not a date", "year"));
+}
+
+#[test]
+fn test_dsl_related_items() {
+    let item = |path: &str, tags: &str, category: &str| ContentItem {
+        path: path.to_string(),
+        slug: format!("/{}", path),
+        meta: [("tags", tags), ("category", category)]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        entry: String::new(),
+        time_to_read: 0,
+        ..Default::default()
+    };
+
+    let items = vec![
+        item("a", "rust, web", "code"),
+        item("b", "Rust", "life"),
+        item("c", "web", "code"),
+        item("d", "cooking", "life"),
+    ];
+
+    let by: ContentDSLRelatedBy =
+        serde_json::from_str(r#"{"meta.tags": 1, "meta.category": 2}"#).unwrap();
+    let related: Vec<String> = dsl_related_items(&items[0], &items, &by, 5)
+        .iter()
+        .map(|item| item.path.clone())
+        .collect();
+    assert_eq!(vec!["c", "b"], related);
+
+    let by: ContentDSLRelatedBy = serde_json::from_str(r#"["tags"]"#).unwrap();
+    let related = dsl_related_items(&items[0], &items, &by, 1);
+    assert_eq!(1, related.len());
+    assert_eq!("b", related[0].path);
+}
+
+#[test]
+fn test_compose_content_navigation() {
+    let site = super::TestSite::new("navigation");
+    site.write("blog/a.md", "---\ntitle: A\n---\n");
+    site.write("blog/b.md", "---\ntitle: B\n---\n");
+    site.write(
+        "content.json",
+        r#"[
+            {"name": "first", "from": "blog/a.md", "navigation": true},
+            {"name": "all", "from": "blog", "sort_by": "meta.title", "order": "asc", "navigation": true}
+        ]"#,
+    );
+
+    let navigation = compose_content_navigation();
+    let a = &navigation[&site.path("blog/a.md")];
+    let b = &navigation[&site.path("blog/b.md")];
+    assert!(a.prev.is_none() && a.next.is_none());
+    assert_eq!("A", b.prev.as_ref().unwrap().meta["title"]);
+}
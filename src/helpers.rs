@@ -1,13 +1,54 @@
-use super::{get_config, TemplateData};
+use super::{assets, get_config, i18n, images, utils};
+use cached::proc_macro::cached;
 use chrono::prelude::*;
-use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext, Renderable};
+use comrak::{markdown_to_html, ComrakOptions};
+use handlebars::{
+    handlebars_helper, Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError,
+    Renderable,
+};
 use regex::Regex;
+use serde_json::{json, Value};
 
-/// Handlebars date helper.
+/// Returns the timezone and locale to format dates of a given helper `h`
+/// with, which are the `tz` and `locale` hash parameters of the helper or
+/// otherwise the site's `TIMEZONE` and `LOCALE`.
+fn date_options(h: &Helper) -> Result<(utils::Timezone, Option<String>), RenderError> {
+    let timezone = match h.hash_get("tz").and_then(|v| v.value().as_str()) {
+        Some(tz) => utils::Timezone::parse(tz).ok_or(RenderError::new(format!("unknown timezone {}", tz)))?,
+        None => utils::site_timezone(),
+    };
+    let locale = h
+        .hash_get("locale")
+        .and_then(|v| v.value().as_str().map(String::from))
+        .or(get_config().locale.clone());
+
+    return Ok((timezone, locale));
+}
+
+/// Parses the date in the first parameter of a given helper `h`, which is an
+/// ISO date, an RFC 3339 date-time or any of the other formats `parse_date`
+/// supports, or a date in the format given by the `input` hash parameter.
+fn date_param(h: &Helper) -> Result<DateTime<FixedOffset>, RenderError> {
+    let value = h
+        .param(0)
+        .and_then(|p| p.value().as_str())
+        .ok_or(RenderError::new(format!("{} requires a date", h.name())))?;
+    let date = match h.hash_get("input").and_then(|v| v.value().as_str()) {
+        Some(input) => utils::parse_date_with_format(value, input),
+        None => utils::parse_date(value),
+    };
+
+    return date.ok_or(RenderError::new(format!("{}: invalid date {}", h.name(), value)));
+}
+
+/// Handlebars date helper, which outputs the current date in a given format,
+/// `%Y-%m-%d` by default, in the site's timezone or a given `tz`, with month
+/// and day names in the site's locale or a given `locale`.
 /// Usage:
 ///
 /// ```handlebars
 /// {{date "%Y %d %m"}}
+/// {{date "%A, %-d %B %Y" tz="Asia/Kolkata" locale="et_EE"}}
 /// ```
 pub fn date_helper(
     h: &Helper,
@@ -16,26 +57,29 @@ pub fn date_helper(
     _rc: &mut RenderContext,
     out: &mut dyn Output,
 ) -> HelperResult {
-    if !h.param(0).unwrap().is_value_missing() {
-        let format: String = serde_json::from_value(h.param(0).unwrap().value().clone()).unwrap();
-        let config = get_config();
-        let hours = config.utc_offset;
-        let offset = FixedOffset::east_opt(hours * 60 * 60)
-            .expect("UTC offset out of bound, min -12, max 12");
-        let dt = Utc::now().with_timezone(&offset);
-        let result = dt.format(&format).to_string();
-
-        out.write(&result)?;
-    }
+    let format = h.param(0).and_then(|p| p.value().as_str()).unwrap_or("%Y-%m-%d");
+    let (timezone, locale) = date_options(h)?;
+    let now = Utc::now().fixed_offset();
+    let result = utils::format_date(&now, format, timezone, locale.as_deref())
+        .map_err(|e| RenderError::new(format!("date: {}", e)))?;
+
+    out.write(&result)?;
 
     Ok(())
 }
 
-/// Handlebars date formatter helper.
+/// Handlebars date formatter helper, which formats a given date in a given
+/// format, `%Y-%m-%d` by default. Dates can be ISO dates, RFC 3339 date-times
+/// or other common formats, or be parsed by a given `input` format. Dates
+/// without an offset are in the site's timezone, and are formatted in the
+/// site's timezone or a given `tz`, with month and day names in the site's
+/// locale or a given `locale`.
 /// Usage:
 ///
 /// ```handlebars
 /// {{format_date date-string "%Y %d %m"}}
+/// {{format_date meta.date "%-d %B %Y, %H:%M" tz="America/New_York" locale="et_EE"}}
+/// {{format_date meta.date "%Y-%m-%d" input="%d/%m/%Y"}}
 /// ```
 pub fn format_date_helper(
     h: &Helper,
@@ -44,92 +88,764 @@ pub fn format_date_helper(
     _rc: &mut RenderContext,
     out: &mut dyn Output,
 ) -> HelperResult {
-    if !h.param(0).unwrap().is_value_missing() {
-        let date: String = serde_json::from_value(h.param(0).unwrap().value().clone()).unwrap();
-        let date_parts: Vec<&str> = date.split("-").collect();
-        let year = date_parts[0].parse::<i32>().unwrap();
-        let month = date_parts[1].parse::<u32>().unwrap();
-        let day = date_parts[2].parse::<u32>().unwrap();
-        let format: String = serde_json::from_value(h.param(1).unwrap().value().clone()).unwrap();
-        let config = get_config();
-        let hours = config.utc_offset;
-        let offset = FixedOffset::east_opt(hours * 60 * 60)
-            .expect("UTC offset out of bound, min -12, max 12");
-        let dt = Utc.ymd(year, month, day).with_timezone(&offset);
-        let result = dt.format(&format).to_string();
-
-        out.write(&result)?;
+    let date = date_param(h)?;
+    let format = h.param(1).and_then(|p| p.value().as_str()).unwrap_or("%Y-%m-%d");
+    let (timezone, locale) = date_options(h)?;
+    let result = utils::format_date(&date, format, timezone, locale.as_deref())
+        .map_err(|e| RenderError::new(format!("format_date: {}", e)))?;
+
+    out.write(&result)?;
+
+    Ok(())
+}
+
+/// Handlebars relative time helper, which describes the time between a given
+/// date and the start of the build in words. Dates are parsed the same way
+/// as with `format_date`.
+/// Usage:
+///
+/// ```handlebars
+/// {{relative_time meta.date}} → 3 days ago
+/// ```
+pub fn relative_time_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _rc: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let date = date_param(h)?;
+
+    out.write(&utils::relative_time(&date, &utils::build_time()))?;
+
+    Ok(())
+}
+
+/// Compiles a given slug `pattern` into a regular expression. Patterns are
+/// regular expressions, or glob patterns when `glob` is enabled, such as
+/// `/blog/*` where `*` matches within a single path segment, and `/blog/**`
+/// which matches `/blog` and everything below it.
+#[cached(size = 256)]
+fn slug_pattern(pattern: String, glob: bool) -> Result<Regex, String> {
+    if !glob {
+        return Regex::new(&pattern).map_err(|e| format!("invalid pattern {}: {}", pattern, e));
+    }
+
+    let mut regex = String::from("^");
+    let mut rest = pattern.as_str();
+
+    while let Some(c) = rest.chars().next() {
+        let (part, len) = if rest.starts_with("/**") && (rest.len() == 3 || rest[3..].starts_with('/')) {
+            ("(?:/.*)?".to_string(), 3)
+        } else if rest.starts_with("**") {
+            (".*".to_string(), 2)
+        } else if c == '*' {
+            ("[^/]*".to_string(), 1)
+        } else if c == '?' {
+            ("[^/]".to_string(), 1)
+        } else {
+            (regex::escape(&c.to_string()), c.len_utf8())
+        };
+
+        regex.push_str(&part);
+        rest = &rest[len..];
+    }
+
+    regex.push('$');
+
+    return Regex::new(&regex).map_err(|e| format!("invalid pattern {}: {}", pattern, e));
+}
+
+/// Returns the URL of a page with a given `slug`, such as `/blog/` for
+/// `/blog/index.html` and `/blog/post/` for `/blog/post`.
+fn page_url(slug: &str) -> String {
+    if let Some(dir) = slug.strip_suffix("index.html") {
+        return dir.to_string();
+    }
+
+    if slug.ends_with(".html") || slug.ends_with('/') {
+        return slug.to_string();
+    }
+
+    return format!("{}/", slug);
+}
+
+/// Determines if the page being rendered with given context `c` matches a
+/// given slug `pattern`, which is matched against both its slug and its URL.
+fn current_page_matches(c: &Context, pattern: &str, glob: bool) -> Result<bool, RenderError> {
+    let regex = slug_pattern(pattern.to_string(), glob).map_err(RenderError::new)?;
+
+    return Ok(match c.data().get("slug").and_then(|slug| slug.as_str()) {
+        Some(slug) => regex.is_match(slug) || regex.is_match(&page_url(slug)),
+        None => false,
+    });
+}
+
+/// Determines if the page being rendered with given context `c` matches the
+/// pattern given as the first parameter of a given helper `h`, which is a
+/// regular expression, or a glob pattern when `glob=true` is given.
+fn slug_helper_matches(h: &Helper, c: &Context) -> Result<bool, RenderError> {
+    let pattern = h
+        .param(0)
+        .and_then(|p| p.value().as_str())
+        .ok_or(RenderError::new(format!("{} requires a pattern", h.name())))?;
+    let glob = h.hash_get("glob").map(|v| v.value().as_bool() == Some(true)).unwrap_or(false);
+
+    return current_page_matches(c, pattern, glob);
+}
+
+/// Renders the block of a given helper `h` when `matches`, and its `{{else}}`
+/// block otherwise.
+fn render_conditional<'reg: 'rc, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    c: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+    matches: bool,
+) -> HelperResult {
+    let template = if matches { h.template() } else { h.inverse() };
+
+    if let Some(template) = template {
+        template.render(r, c, rc, out)?;
     }
 
     Ok(())
 }
 
-/// Handlebars slug checking helper.
+/// Handlebars slug checking helper, which renders its block when the slug or
+/// URL of the current page matches a given regular expression, or a glob
+/// pattern with `glob=true`, and its `{{else}}` block otherwise.
 /// Usage:
 ///
 /// ```handlebars
 /// {{#is_slug "/archive/index.html"}}
 /// // my code goes here
+/// {{else}}
+/// // or here
 /// {{/is_slug}}
+/// {{#is_slug "/blog/**" glob=true}}...{{/is_slug}}
 /// ```
-pub fn is_slug_helper(
+pub fn is_slug_helper<'reg, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    c: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let matches = slug_helper_matches(h, c)?;
+
+    return render_conditional(h, r, c, rc, out, matches);
+}
+
+/// Handlebars slug checking helper, the opposite of `is_slug`, which renders
+/// its block when the current page doesn't match a given pattern.
+/// Usage:
+///
+/// ```handlebars
+/// {{#unless_slug "/archive/index.html"}}
+/// // my code goes here
+/// {{/unless_slug}}
+/// ```
+pub fn unless_slug_helper<'reg, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    c: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let matches = slug_helper_matches(h, c)?;
+
+    return render_conditional(h, r, c, rc, out, !matches);
+}
+
+/// Handlebars section checking helper, which renders its block when the
+/// current page is a given section, such as `/blog`, or within it, and its
+/// `{{else}}` block otherwise.
+/// Usage:
+///
+/// ```handlebars
+/// {{#is_section "blog"}}
+/// // my code goes here
+/// {{/is_section}}
+/// ```
+pub fn is_section_helper<'reg, 'rc>(
+    h: &Helper<'reg, 'rc>,
+    r: &'reg Handlebars<'reg>,
+    c: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let section = h
+        .param(0)
+        .and_then(|p| p.value().as_str())
+        .ok_or(RenderError::new("is_section requires a section"))?;
+    let pattern = format!("/{}/**", section.trim_matches('/'));
+    let matches = current_page_matches(c, &pattern, true)?;
+
+    return render_conditional(h, r, c, rc, out, matches);
+}
+
+/// Handlebars active class helper for navigation menus, which outputs a class
+/// name, `active` by default, when the slug or URL of the current page matches
+/// a given glob pattern. Regular expressions can be used with `regex=true`.
+/// Usage:
+///
+/// ```handlebars
+/// <a href="/blog/" class="{{active_class "/blog/**"}}">Blog</a>
+/// <a href="/" class="{{active_class "/" class="is-current"}}">Home</a>
+/// ```
+pub fn active_class_helper(
     h: &Helper,
-    r: &Handlebars,
+    _: &Handlebars,
     c: &Context,
-    rc: &mut RenderContext,
+    _rc: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let pattern = h
+        .param(0)
+        .and_then(|p| p.value().as_str())
+        .ok_or(RenderError::new("active_class requires a pattern"))?;
+    let regex = h.hash_get("regex").map(|v| v.value().as_bool() == Some(true)).unwrap_or(false);
+    let class = h.hash_get("class").and_then(|v| v.value().as_str()).unwrap_or("active");
+
+    if current_page_matches(c, pattern, !regex)? {
+        out.write(class)?;
+    }
+
+    Ok(())
+}
+
+/// Handlebars asset URL helper, which resolves the logical path of an asset
+/// into its fingerprinted path when fingerprinting is enabled.
+/// Usage:
+///
+/// ```handlebars
+/// <link rel="stylesheet" href="{{asset "/css/style.css"}}">
+/// ```
+pub fn asset_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _rc: &mut RenderContext,
     out: &mut dyn Output,
 ) -> HelperResult {
-    let mut x = rc.clone();
+    if let Some(path) = h.param(0).and_then(|p| p.value().as_str()) {
+        out.write(&assets::resolve_asset_path(path))?;
+    }
 
-    if !h.param(0).unwrap().is_value_missing() {
-        let path: String = serde_json::from_value(h.param(0).unwrap().value().clone()).unwrap();
-        let data: TemplateData = serde_json::from_value(c.data().clone()).unwrap();
-        let slug = data.slug;
-        let regex = Regex::new(&path);
+    Ok(())
+}
+
+/// Handlebars asset SRI integrity helper.
+/// Usage:
+///
+/// ```handlebars
+/// <script src="{{asset "/js/app.js"}}" integrity="{{asset_integrity "/js/app.js"}}"></script>
+/// ```
+pub fn asset_integrity_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _rc: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    if let Some(path) = h.param(0).and_then(|p| p.value().as_str()) {
+        let logical_path = format!("/{}", path.trim_start_matches('/'));
 
-        if (regex.is_err() || slug.is_none()) && h.inverse().is_some() {
-            h.inverse().unwrap();
+        if let Some(entry) = assets::asset_manifest().get(&logical_path) {
+            out.write(&entry.integrity)?;
         }
+    }
 
-        if slug.is_some() && regex.unwrap().is_match(&slug.unwrap()) && h.template().is_some() {
-            h.template().unwrap().render(&r, &c, &mut x, out).unwrap();
+    Ok(())
+}
+
+/// Handlebars responsive image helper, which renders an image with a
+/// `srcset` of its generated variants, its width and height, and a `picture`
+/// source for each of the additionally generated formats. Any other hash
+/// parameters are added as attributes.
+/// Usage:
+///
+/// ```handlebars
+/// {{image "/images/photo.jpg" alt="A photo" sizes="(min-width: 800px) 800px, 100vw"}}
+/// ```
+pub fn image_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _rc: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    if let Some(path) = h.param(0).and_then(|p| p.value().as_str()) {
+        let hash_str = |key: &str| h.hash_get(key).and_then(|v| v.value().as_str().map(String::from));
+        let alt = hash_str("alt").unwrap_or_default();
+        let sizes = hash_str("sizes").unwrap_or(get_config().image_sizes.clone());
+        let mut attrs: Vec<(String, String)> = Vec::new();
+
+        for (name, value) in h.hash() {
+            if *name != "alt" && *name != "sizes" {
+                let value = match value.value() {
+                    serde_json::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+
+                attrs.push((name.to_string(), value));
+            }
         }
+
+        attrs.sort();
+        out.write(&images::render_image(path, &alt, &sizes, &attrs))?;
     }
 
     Ok(())
 }
 
-/// Handlebars slug checking helper.
+/// Handlebars translation helper, which outputs the translation of a given
+/// key into the language of the current page, or a given `lang`, from the
+/// `_i18n/<lang>.yaml` files. Falls back to the default language and then to
+/// the key itself. Other hash parameters fill placeholders such as `{count}`.
 /// Usage:
 ///
 /// ```handlebars
-/// {{#unless_slug "/archive/index.html"}}
-/// // my code goes here
-/// {{/unless_slug}}
+/// {{t "nav.home"}}
+/// {{t "posts_count" count=(length content.posts)}}
 /// ```
-pub fn unless_slug_helper(
+pub fn t_helper(
     h: &Helper,
-    r: &Handlebars,
+    _: &Handlebars,
     c: &Context,
-    rc: &mut RenderContext,
+    _rc: &mut RenderContext,
     out: &mut dyn Output,
 ) -> HelperResult {
-    let mut x = rc.clone();
+    let key = h
+        .param(0)
+        .and_then(|p| p.value().as_str())
+        .ok_or(RenderError::new("t requires a key"))?;
+    let lang = h
+        .hash_get("lang")
+        .and_then(|v| v.value().as_str().map(String::from))
+        .or(c.data().get("lang").and_then(|lang| lang.as_str().map(String::from)))
+        .unwrap_or(i18n::default_language());
+    let params: Vec<(String, String)> = h
+        .hash()
+        .iter()
+        .filter(|(name, _)| **name != "lang")
+        .map(|(name, value)| (name.to_string(), json_string(value.value())))
+        .collect();
+
+    out.write(&i18n::translate(&lang, key, &params))?;
+
+    Ok(())
+}
+
+/// Returns the field of given `value` at a given dot-separated `key`, such as
+/// `meta.title`, or null when there is no such field.
+fn json_field(value: &Value, key: &str) -> Value {
+    let mut target = value;
+
+    for segment in key.split('.').filter(|s| !s.is_empty()) {
+        let next = match target {
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => target.get(segment),
+        };
+
+        match next {
+            Some(next) => target = next,
+            None => return Value::Null,
+        }
+    }
+
+    return target.clone();
+}
+
+/// Returns given `value` as a string, without quotes for strings and empty
+/// for null.
+fn json_string(value: &Value) -> String {
+    return match value {
+        Value::String(value) => value.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    };
+}
+
+/// Returns given `number` as JSON, as an integer when it has no fraction.
+fn json_number(number: f64) -> Value {
+    if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+        return json!(number as i64);
+    }
+
+    return json!(number);
+}
+
+/// Turns given `text` into a URL-friendly slug.
+/// Usage:
+///
+/// ```handlebars
+/// {{slugify "Hello, World!"}} → hello-world
+/// ```
+pub fn slugify(text: &str) -> String {
+    return text
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-");
+}
+
+/// Truncates given `text` to a given `length` in characters, ending it with
+/// a `suffix` when it was truncated.
+/// Usage:
+///
+/// ```handlebars
+/// {{truncate meta.description 120}}
+/// {{truncate meta.description 120 suffix="..."}}
+/// ```
+pub fn truncate(text: &str, length: usize, suffix: &str) -> String {
+    if text.chars().count() <= length {
+        return text.to_string();
+    }
+
+    let truncated: String = text.chars().take(length).collect();
+
+    return format!("{}{}", truncated.trim_end(), suffix);
+}
+
+/// Returns the number of items in given `value`, which is the number of
+/// elements of an array, keys of an object or characters of a string.
+/// Usage:
+///
+/// ```handlebars
+/// {{length content.posts}} posts
+/// ```
+pub fn length(value: &Value) -> usize {
+    return match value {
+        Value::Array(items) => items.len(),
+        Value::Object(map) => map.len(),
+        Value::String(text) => text.chars().count(),
+        _ => 0,
+    };
+}
 
-    if !h.param(0).unwrap().is_value_missing() {
-        let path: String = serde_json::from_value(h.param(0).unwrap().value().clone()).unwrap();
-        let data: TemplateData = serde_json::from_value(c.data().clone()).unwrap();
-        let slug = data.slug;
-        let regex = Regex::new(&path);
+/// Returns the items of given `value` from the `start` index up to but not
+/// including the `end` index, for arrays as well as strings.
+/// Usage:
+///
+/// ```handlebars
+/// {{#each (slice content.posts 0 3)}}{{meta.title}}{{/each}}
+/// ```
+pub fn slice(value: &Value, start: usize, end: usize) -> Value {
+    return match value {
+        Value::Array(items) => {
+            let end = end.min(items.len());
+            Value::Array(items.get(start.min(end)..end).unwrap_or_default().to_vec())
+        }
+        Value::String(text) => Value::String(
+            text.chars()
+                .skip(start)
+                .take(end.saturating_sub(start))
+                .collect(),
+        ),
+        _ => Value::Null,
+    };
+}
 
-        if regex.is_err() || slug.is_none() {
-            h.inverse().unwrap();
+/// Sorts the items of given array `value` by the field at a given `key`,
+/// such as `meta.date`, comparing numbers, dates and text naturally. The
+/// `order` is either `asc` or `desc`.
+/// Usage:
+///
+/// ```handlebars
+/// {{#each (sort_by content.posts "meta.date" order="desc")}}{{meta.title}}{{/each}}
+/// ```
+pub fn sort_by(value: &Value, key: &str, order: &str) -> Value {
+    let mut items = value.as_array().cloned().unwrap_or_default();
+    let values: Vec<String> = items
+        .iter()
+        .map(|item| json_string(&json_field(item, key)))
+        .filter(|value| !value.is_empty())
+        .collect();
+    let compare = utils::column_compare(&values, "auto");
+
+    items.sort_by(|a, b| {
+        let ordering = utils::compare_values(
+            &json_string(&json_field(a, key)),
+            &json_string(&json_field(b, key)),
+            &compare,
+        );
+
+        if order == "desc" {
+            ordering.reverse()
+        } else {
+            ordering
         }
+    });
+
+    return Value::Array(items);
+}
+
+/// Returns the items of given array `value` whose field at a given `key` is
+/// equal to `expected`, or contains it when the field is an array. Values
+/// are compared as text, so that `"10"` matches `10`.
+/// Usage:
+///
+/// ```handlebars
+/// {{#each (where content.posts "meta.category" "rust")}}{{meta.title}}{{/each}}
+/// ```
+pub fn where_eq(value: &Value, key: &str, expected: &Value) -> Value {
+    let expected = json_string(expected);
+    let items = value.as_array().cloned().unwrap_or_default();
+
+    return Value::Array(
+        items
+            .into_iter()
+            .filter(|item| match json_field(item, key) {
+                Value::Array(values) => values.iter().any(|v| json_string(v) == expected),
+                field => json_string(&field) == expected,
+            })
+            .collect(),
+    );
+}
+
+/// Determines if given `value` contains a `needle`, which is a substring of
+/// a string, an element of an array or a key of an object.
+/// Usage:
+///
+/// ```handlebars
+/// {{#if (contains meta.tags "rust")}}🦀{{/if}}
+/// ```
+pub fn contains(value: &Value, needle: &Value) -> bool {
+    return match value {
+        Value::String(text) => text.contains(&json_string(needle)),
+        Value::Array(items) => items
+            .iter()
+            .any(|item| item == needle || json_string(item) == json_string(needle)),
+        Value::Object(map) => map.contains_key(&json_string(needle)),
+        _ => false,
+    };
+}
+
+/// Renders given Markdown `text` into HTML. Text that makes up a single
+/// paragraph is rendered without the surrounding `<p>` element, so that it
+/// can be used inline.
+/// Usage:
+///
+/// ```handlebars
+/// <h1>{{{markdown meta.title}}}</h1>
+/// ```
+pub fn markdown(text: &str) -> String {
+    let mut opts = ComrakOptions::default();
+    opts.render.unsafe_ = true;
+
+    let html = markdown_to_html(text, &opts);
+    let trimmed = html.trim();
 
-        if slug.is_some() && !regex.unwrap().is_match(&slug.unwrap()) && h.template().is_some() {
-            h.template().unwrap().render(&r, &c, &mut x, out).unwrap();
+    if let Some(inner) = trimmed.strip_prefix("<p>").and_then(|t| t.strip_suffix("</p>")) {
+        if !inner.contains("<p>") {
+            return inner.to_string();
         }
     }
 
-    Ok(())
+    return trimmed.to_string();
+}
+
+/// Percent-encodes given `text` for use within a URL.
+/// Usage:
+///
+/// ```handlebars
+/// <a href="https://twitter.com/intent/tweet?text={{url_encode meta.title}}">Tweet</a>
+/// ```
+pub fn url_encode(text: &str) -> String {
+    return text
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect();
+}
+
+// The standard helper library. Comparison and logic helpers (`eq`, `ne`,
+// `gt`, `gte`, `lt`, `lte`, `and`, `or`, `not`) are built into Handlebars.
+// Usage of the helpers without a function of their own:
+//
+// {{upper "text"}}, {{lower "TEXT"}}        → TEXT, text
+// {{replace meta.title "-" " "}}            → replaces all occurrences
+// {{{json meta}}}, {{{json meta pretty=true}}}
+// {{default meta.subtitle "Untitled"}}      → the fallback for empty values
+// {{first content.posts}}, {{last content.posts}}
+// {{join meta.tags ", "}}, {{split meta.tags ","}}
+// {{add 1 2}}, {{sub 3 1}}                  → 3, 2
+handlebars_helper!(slugify_helper: |text: str| slugify(text));
+handlebars_helper!(truncate_helper: |text: str, length: u64, {suffix: str = "…"}| {
+    truncate(text, length as usize, suffix)
+});
+handlebars_helper!(upper_helper: |text: str| text.to_uppercase());
+handlebars_helper!(lower_helper: |text: str| text.to_lowercase());
+handlebars_helper!(replace_helper: |text: str, from: str, to: str| text.replace(from, to));
+handlebars_helper!(json_helper: |value: Json, {pretty: bool = false}| {
+    if pretty {
+        serde_json::to_string_pretty(value).unwrap_or_default()
+    } else {
+        value.to_string()
+    }
+});
+handlebars_helper!(default_helper: |value: Json, fallback: Json| {
+    match value {
+        Value::Null => fallback.clone(),
+        Value::String(text) if text.is_empty() => fallback.clone(),
+        value => value.clone(),
+    }
+});
+handlebars_helper!(length_helper: |value: Json| length(value));
+handlebars_helper!(first_helper: |value: Json| {
+    value.as_array().and_then(|items| items.first()).cloned().unwrap_or(Value::Null)
+});
+handlebars_helper!(last_helper: |value: Json| {
+    value.as_array().and_then(|items| items.last()).cloned().unwrap_or(Value::Null)
+});
+handlebars_helper!(slice_helper: |value: Json, start: u64, end: u64| {
+    slice(value, start as usize, end as usize)
+});
+handlebars_helper!(sort_by_helper: |value: Json, key: str, {order: str = "asc"}| {
+    sort_by(value, key, order)
+});
+handlebars_helper!(where_helper: |value: Json, key: str, expected: Json| where_eq(value, key, expected));
+handlebars_helper!(join_helper: |value: Json, separator: str| {
+    value
+        .as_array()
+        .map(|items| items.iter().map(json_string).collect::<Vec<String>>().join(separator))
+        .unwrap_or(json_string(value))
+});
+handlebars_helper!(split_helper: |text: str, separator: str| {
+    text.split(separator).map(|part| part.trim().to_string()).collect::<Vec<String>>()
+});
+handlebars_helper!(contains_helper: |value: Json, needle: Json| contains(value, needle));
+handlebars_helper!(add_helper: |a: f64, b: f64| json_number(a + b));
+handlebars_helper!(sub_helper: |a: f64, b: f64| json_number(a - b));
+handlebars_helper!(markdown_helper: |text: str| markdown(text));
+handlebars_helper!(url_encode_helper: |text: str| url_encode(text));
+
+/// Registers the standard helper library on given `hbs`.
+pub fn register_standard_helpers(hbs: &mut Handlebars) {
+    hbs.register_helper("slugify", Box::new(slugify_helper));
+    hbs.register_helper("truncate", Box::new(truncate_helper));
+    hbs.register_helper("upper", Box::new(upper_helper));
+    hbs.register_helper("lower", Box::new(lower_helper));
+    hbs.register_helper("replace", Box::new(replace_helper));
+    hbs.register_helper("json", Box::new(json_helper));
+    hbs.register_helper("default", Box::new(default_helper));
+    hbs.register_helper("length", Box::new(length_helper));
+    hbs.register_helper("first", Box::new(first_helper));
+    hbs.register_helper("last", Box::new(last_helper));
+    hbs.register_helper("slice", Box::new(slice_helper));
+    hbs.register_helper("sort_by", Box::new(sort_by_helper));
+    hbs.register_helper("where", Box::new(where_helper));
+    hbs.register_helper("join", Box::new(join_helper));
+    hbs.register_helper("split", Box::new(split_helper));
+    hbs.register_helper("contains", Box::new(contains_helper));
+    hbs.register_helper("add", Box::new(add_helper));
+    hbs.register_helper("sub", Box::new(sub_helper));
+    hbs.register_helper("markdown", Box::new(markdown_helper));
+    hbs.register_helper("url_encode", Box::new(url_encode_helper));
+}
+
+#[test]
+fn test_string_helpers() {
+    assert_eq!("hello-world", slugify("Hello, World!"));
+    assert_eq!("crème-brûlée-2", slugify("  Crème Brûlée #2 "));
+    assert_eq!("Hello…", truncate("Hello world", 6, "…"));
+    assert_eq!("Hello world", truncate("Hello world", 20, "…"));
+    assert_eq!("Hello%20w%C3%B6rld%21", url_encode("Hello wörld!"));
+    assert_eq!("<em>Hi</em> there", markdown("*Hi* there"));
+    assert_eq!("<p>One</p>\n<p>Two</p>", markdown("One\n\nTwo"));
+}
+
+#[test]
+fn test_collection_helpers() {
+    let posts = json!([
+        {"meta": {"title": "B", "order": "10", "tags": ["rust"]}},
+        {"meta": {"title": "A", "order": "9", "tags": ["web"]}},
+        {"meta": {"title": "C", "order": "11"}},
+    ]);
+    let titles = |value: Value| -> Vec<String> {
+        value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| json_string(&json_field(item, "meta.title")))
+            .collect()
+    };
+
+    assert_eq!(3, length(&posts));
+    assert_eq!(4, length(&json!("four")));
+    assert_eq!(vec!["A", "C"], titles(slice(&posts, 1, 5)));
+    assert_eq!(json!("ou"), slice(&json!("four"), 1, 3));
+    assert_eq!(vec!["A", "B", "C"], titles(sort_by(&posts, "meta.order", "asc")));
+    assert_eq!(vec!["C", "B", "A"], titles(sort_by(&posts, "meta.order", "desc")));
+    assert_eq!(vec!["B"], titles(where_eq(&posts, "meta.order", &json!(10))));
+    assert_eq!(vec!["A"], titles(where_eq(&posts, "meta.tags", &json!("web"))));
+    assert!(contains(&json!(["a", "b"]), &json!("b")));
+    assert!(contains(&json!("rustacean"), &json!("rust")));
+    assert!(contains(&json!({"a": 1}), &json!("a")));
+    assert!(!contains(&json!([1, 2]), &json!(3)));
+}
+
+#[test]
+fn test_standard_helpers() {
+    let mut hbs = Handlebars::new();
+    register_standard_helpers(&mut hbs);
+
+    let data = json!({
+        "title": "Hello World",
+        "tags": ["rust", "web"],
+        "csv": "a, b",
+        "empty": "",
+        "posts": [{"n": 2}, {"n": 1}],
+    });
+    let render = |template: &str| hbs.render_template(template, &data).unwrap();
+
+    assert_eq!("hello-world", render("{{slugify title}}"));
+    assert_eq!("Hello...", render("{{truncate title 5 suffix=\"...\"}}"));
+    assert_eq!("HELLO WORLD hello world", render("{{upper title}} {{lower title}}"));
+    assert_eq!("Hello_World", render("{{replace title \" \" \"_\"}}"));
+    assert_eq!("[\"rust\",\"web\"]", render("{{{json tags}}}"));
+    assert_eq!("Untitled", render("{{default missing \"Untitled\"}}"));
+    assert_eq!("Untitled", render("{{default empty \"Untitled\"}}"));
+    assert_eq!("Hello World", render("{{default title \"Untitled\"}}"));
+    assert_eq!("2 rust web", render("{{length tags}} {{first tags}} {{last tags}}"));
+    assert_eq!("web", render("{{#each (slice tags 1 2)}}{{this}}{{/each}}"));
+    assert_eq!("12", render("{{#each (sort_by posts \"n\")}}{{n}}{{/each}}"));
+    assert_eq!("1", render("{{#each (where posts \"n\" 1)}}{{n}}{{/each}}"));
+    assert_eq!("rust, web|a|b", render("{{join tags \", \"}}|{{join (split csv \",\") \"|\"}}"));
+    assert_eq!("yes", render("{{#if (contains tags \"rust\")}}yes{{/if}}"));
+    assert_eq!("3 1 1.5", render("{{add 1 2}} {{sub 3 2}} {{add 1 0.5}}"));
+    assert_eq!("<strong>Hi</strong>", render("{{{markdown \"**Hi**\"}}}"));
+    assert_eq!("Hello%20World", render("{{url_encode title}}"));
+    assert_eq!("true false", render("{{eq 1 1}} {{and true false}}"));
+}
+
+#[test]
+fn test_slug_helpers() {
+    let mut hbs = Handlebars::new();
+    hbs.register_helper("is_slug", Box::new(is_slug_helper));
+    hbs.register_helper("unless_slug", Box::new(unless_slug_helper));
+    hbs.register_helper("is_section", Box::new(is_section_helper));
+    hbs.register_helper("active_class", Box::new(active_class_helper));
+
+    let render = |template: &str, slug: &str| hbs.render_template(template, &json!({ "slug": slug }));
+
+    assert_eq!("yes", render("{{#is_slug \"^/blog\"}}yes{{else}}no{{/is_slug}}", "/blog/post").unwrap());
+    assert_eq!("no", render("{{#is_slug \"^/docs\"}}yes{{else}}no{{/is_slug}}", "/blog/post").unwrap());
+    assert_eq!("yes", render("{{#unless_slug \"^/docs\"}}yes{{else}}no{{/unless_slug}}", "/blog/post").unwrap());
+    assert_eq!("no", render("{{#is_slug \"/blog/*\" glob=true}}yes{{else}}no{{/is_slug}}", "/blog/a/b").unwrap());
+    assert_eq!("yes", render("{{#is_slug \"/blog/**\" glob=true}}yes{{/is_slug}}", "/blog/a/b").unwrap());
+    assert_eq!("yes", render("{{#is_section \"blog\"}}yes{{/is_section}}", "/blog/index.html").unwrap());
+    assert_eq!("no", render("{{#is_section \"blog\"}}yes{{else}}no{{/is_section}}", "/blogroll").unwrap());
+    assert_eq!("active", render("{{active_class \"/\"}}", "/index.html").unwrap());
+    assert_eq!("", render("{{active_class \"/\"}}", "/about.html").unwrap());
+    assert_eq!("on", render("{{active_class \"/blog/*/\" class=\"on\"}}", "/blog/post").unwrap());
+    assert!(render("{{#is_slug \"(\"}}yes{{/is_slug}}", "/").is_err());
 }
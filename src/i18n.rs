@@ -0,0 +1,263 @@
+use super::{
+    content_slug, find_files, get_config, merge_json, parse_content_file_meta, site_dirs, template_slug,
+    FileType,
+};
+use cached::proc_macro::cached;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentTranslation {
+    pub lang: String,
+    pub slug: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TranslationIndex {
+    pub keys: HashMap<String, String>,
+    pub translations: HashMap<String, Vec<ContentTranslation>>,
+}
+
+/// Returns the default language of the site, which is the first of the
+/// configured `languages`, or `en` when none are configured.
+pub fn default_language() -> String {
+    return get_config()
+        .languages
+        .first()
+        .cloned()
+        .unwrap_or(String::from("en"));
+}
+
+/// Splits a given path `relative_path` to the root directory into the
+/// language of the file and the path without the language. The language is
+/// given by a language directory, such as `/et/blog/post.md`, or by a
+/// language suffix, such as `/blog/post.et.md`. Files without either are in
+/// the default language. Only the configured `languages` are recognized.
+pub fn split_language(relative_path: &str) -> (String, String) {
+    let config = get_config();
+
+    for lang in &config.languages {
+        if let Some(rest) = relative_path.strip_prefix(&format!("/{}/", lang)) {
+            return (lang.clone(), format!("/{}", rest));
+        }
+    }
+
+    let (dir, file_name) = relative_path.rsplit_once('/').unwrap_or(("", relative_path));
+    let mut parts: Vec<&str> = file_name.split('.').collect();
+
+    if parts.len() >= 3 && config.languages.iter().any(|lang| lang == parts[1]) {
+        let lang = parts.remove(1).to_string();
+
+        return (lang, format!("{}/{}", dir, parts.join(".")));
+    }
+
+    return (default_language(), relative_path.to_string());
+}
+
+/// Returns the slug of a page in a given `lang` with a given `slug`, which
+/// is prefixed with the language unless it is the default language, such as
+/// `/et/blog/post` for `/blog/post`.
+pub fn localized_slug(lang: &str, slug: &str) -> String {
+    if lang == default_language() {
+        return slug.to_string();
+    }
+
+    return format!("/{}{}", lang, slug);
+}
+
+/// Returns a given localized `slug` of a page in a given `lang` without the
+/// language prefix, which is the same for all translations of the page.
+fn unlocalized_slug(lang: &str, slug: &str) -> String {
+    if lang == default_language() {
+        return slug.to_string();
+    }
+
+    return slug.strip_prefix(&format!("/{}", lang)).unwrap_or(slug).to_string();
+}
+
+/// Builds the index of the translations of all content files and Handlebars
+/// pages within the root directory by their translation key, which is their
+/// slug without the language, or a `translation_key` given in their front
+/// matter. Maps the path of every file to its translation key, and every
+/// translation key to the translations in the order of the `languages`.
+#[cached(time = 2)]
+pub(crate) fn translation_index() -> Arc<TranslationIndex> {
+    let config = get_config();
+    let dir = &config.dir;
+    let languages = &config.languages;
+    let mut index = TranslationIndex::default();
+    let markdown_files = find_files(dir.clone(), FileType::Markdown);
+    let page_files = find_files(dir.clone(), FileType::HandlebarsPages);
+    let files = markdown_files
+        .iter()
+        .map(|file| (file, content_slug(file)))
+        .chain(page_files.iter().map(|file| (file, template_slug(file))));
+
+    for (file, slug) in files {
+        let contents = fs::read_to_string(file).unwrap_or_default();
+        let meta = parse_content_file_meta(contents).unwrap_or_default();
+        let (lang, _) = split_language(&file.replace(dir, ""));
+        let key = meta
+            .get("translation_key")
+            .cloned()
+            .unwrap_or(unlocalized_slug(&lang, &slug));
+
+        index.keys.insert(file.clone(), key.clone());
+        index.translations.entry(key).or_default().push(ContentTranslation {
+            title: meta.get("title").cloned().unwrap_or(slug.clone()),
+            lang,
+            slug,
+        });
+    }
+
+    for items in index.translations.values_mut() {
+        items.sort_by_key(|item| languages.iter().position(|lang| *lang == item.lang));
+    }
+
+    return Arc::new(index);
+}
+
+/// Returns the translations of a content file or Handlebars page in a given
+/// `path` into the other languages.
+pub fn find_translations(path: &str) -> Vec<ContentTranslation> {
+    let index = translation_index();
+    let (lang, _) = split_language(&path.replace(&get_config().dir, ""));
+
+    return match index.keys.get(path).and_then(|key| index.translations.get(key)) {
+        Some(translations) => translations.iter().filter(|t| t.lang != lang).cloned().collect(),
+        None => Vec::new(),
+    };
+}
+
+/// Returns the translation strings of a given `lang`, read from the
+/// `_i18n/<lang>.yaml` file of the site on top of those of its themes. Missing
+/// and empty files have no strings.
+#[cached(time = 2)]
+pub(crate) fn translation_strings(lang: String) -> Value {
+    let mut strings = serde_json::json!({});
+
+    for dir in site_dirs().iter().rev() {
+        // Neither the site nor a theme has to translate every language
+        let contents = match fs::read_to_string(format!("{}/_i18n/{}.yaml", dir, lang)) {
+            Ok(contents) if !contents.trim().is_empty() => contents,
+            _ => continue,
+        };
+
+        match serde_yaml::from_str::<Value>(&contents) {
+            Ok(Value::Null) => (),
+            Ok(value) => merge_json(&mut strings, value),
+            Err(err) => println!("Could not read translations _i18n/{}.yaml: {}", lang, err),
+        }
+    }
+
+    return strings;
+}
+
+/// Looks up a given dot-separated `key`, such as `nav.home`, in given
+/// translation `strings`.
+fn lookup(strings: &Value, key: &str) -> Option<String> {
+    let mut value = strings;
+
+    for segment in key.split('.') {
+        value = value.get(segment)?;
+    }
+
+    return match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Null | Value::Object(_) | Value::Array(_) => None,
+        value => Some(value.to_string()),
+    };
+}
+
+/// Translates a given `key` into a given `lang`, falling back to the default
+/// language and then to the key itself. Placeholders in the translation,
+/// such as `{count}`, are replaced with the given `params`.
+pub fn translate(lang: &str, key: &str, params: &[(String, String)]) -> String {
+    let mut text = lookup(&translation_strings(lang.to_string()), key)
+        .or_else(|| lookup(&translation_strings(default_language()), key))
+        .unwrap_or(key.to_string());
+
+    for (name, value) in params {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+
+    return text;
+}
+
+#[test]
+fn test_lookup() {
+    let strings = serde_json::json!({"nav": {"home": "Avaleht"}, "count": 3});
+
+    assert_eq!(Some(String::from("Avaleht")), lookup(&strings, "nav.home"));
+    assert_eq!(Some(String::from("3")), lookup(&strings, "count"));
+    assert_eq!(None, lookup(&strings, "nav"));
+    assert_eq!(None, lookup(&strings, "nav.about"));
+}
+
+#[test]
+fn test_split_language() {
+    let mut config = super::test_config("/site");
+    config.languages = vec![String::from("en"), String::from("et")];
+    let _config = super::lock_config(config);
+
+    assert_eq!((String::from("et"), String::from("/blog/post.md")), split_language("/et/blog/post.md"));
+    assert_eq!((String::from("et"), String::from("/blog/post.md")), split_language("/blog/post.et.md"));
+    assert_eq!((String::from("et"), String::from("/index.html.hbs")), split_language("/index.et.html.hbs"));
+    assert_eq!((String::from("en"), String::from("/blog/post.de.md")), split_language("/blog/post.de.md"));
+    assert_eq!((String::from("en"), String::from("/etc/post.md")), split_language("/etc/post.md"));
+    assert_eq!((String::from("en"), String::from("/et.md")), split_language("/et.md"));
+    assert_eq!("/blog/post", localized_slug("en", "/blog/post"));
+    assert_eq!("/et/blog/post", localized_slug("et", "/blog/post"));
+    assert_eq!("/blog/post", unlocalized_slug("et", "/et/blog/post"));
+}
+
+#[test]
+fn test_find_translations() {
+    let site = super::TestSite::new("translations");
+    site.write("blog/post.md", "---\ntitle: Post\n---\n");
+    site.write("et/blog/post.md", "---\ntitle: Postitus\n---\n");
+    site.write("blog/about.md", "---\ntitle: About\ntranslation_key: about\n---\n");
+    site.write("blog/meist.et.md", "---\ntitle: Meist\ntranslation_key: about\n---\n");
+    site.write("blog/alone.md", "---\ntitle: Alone\n---\n");
+    site.configure(|config| config.languages = vec![String::from("en"), String::from("et")]);
+
+    let translation = |lang: &str, slug: &str, title: &str| ContentTranslation {
+        lang: lang.to_string(),
+        slug: slug.to_string(),
+        title: title.to_string(),
+    };
+
+    assert_eq!(
+        vec![translation("et", "/et/blog/post", "Postitus")],
+        find_translations(&site.path("blog/post.md"))
+    );
+    assert_eq!(
+        vec![translation("en", "/blog/post", "Post")],
+        find_translations(&site.path("et/blog/post.md"))
+    );
+    assert_eq!(
+        vec![translation("en", "/blog/about", "About")],
+        find_translations(&site.path("blog/meist.et.md"))
+    );
+    assert!(find_translations(&site.path("blog/alone.md")).is_empty());
+}
+
+#[test]
+fn test_translation_strings() {
+    let site = super::TestSite::new("translation-strings");
+    site.write("_i18n/et.yaml", "nav:\n  home: Avaleht\n");
+    site.write("_i18n/de.yaml", "\n");
+    site.write("themes/mini/_i18n/et.yaml", "nav:\n  home: Kodu\n  about: Meist\n");
+    site.configure(|config| config.themes = vec![super::resolve_theme_dir(&site.dir, "mini")]);
+
+    assert_eq!(
+        serde_json::json!({"nav": {"home": "Avaleht", "about": "Meist"}}),
+        translation_strings(String::from("et"))
+    );
+    assert_eq!(serde_json::json!({}), translation_strings(String::from("de")));
+    assert_eq!(serde_json::json!({}), translation_strings(String::from("fr")));
+}
@@ -0,0 +1,384 @@
+use super::{find_site_files, get_config, links, relative_path, resolve_site_path, FileType};
+use cached::proc_macro::cached;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use rayon::prelude::*;
+use regex::{Captures, Regex};
+use sha2::{Digest, Sha384};
+use std::fs;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageVariant {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+}
+
+/// Determines if the given `path` matches an image that can be processed.
+pub fn is_processable_image(path: &str) -> bool {
+    let lower = path.to_lowercase();
+
+    return lower.ends_with(".jpg")
+        || lower.ends_with(".jpeg")
+        || lower.ends_with(".png")
+        || lower.ends_with(".webp");
+}
+
+/// Returns the format of an image in a given `path`, such as `jpeg`.
+fn image_format(path: &str) -> String {
+    let extension = path.rsplit('.').next().unwrap_or_default().to_lowercase();
+
+    return if extension == "jpg" {
+        String::from("jpeg")
+    } else {
+        extension
+    };
+}
+
+/// Returns the width and height of the source image of a given image `path`,
+/// which is relative to the root directory or one of the themes.
+#[cached(time = 2)]
+pub fn image_dimensions(path: String) -> Option<(u32, u32)> {
+    return image::image_dimensions(resolve_site_path(&path)).ok();
+}
+
+/// Returns the formats that variants of an image in a given `path` are
+/// generated in, which are the image's own format and each of the
+/// `IMAGE_FORMATS`. WebP can only be encoded losslessly, which makes WebP
+/// variants of lossy JPEG images larger than the JPEG variants, so JPEG
+/// images don't get them.
+fn variant_formats(path: &str) -> Vec<String> {
+    let format = image_format(path);
+    let mut formats = vec![format.clone()];
+
+    for other in &get_config().image_formats {
+        let is_lossy_webp = format == "jpeg" && other == "webp";
+
+        if !formats.contains(other) && !is_lossy_webp {
+            formats.push(other.clone());
+        }
+    }
+
+    return formats;
+}
+
+/// Returns the path of a variant of an image in a given `path` with a given
+/// `width` and `format`, such as `/images/photo-480w.webp` for
+/// `/images/photo.jpg`.
+fn variant_path(path: &str, width: u32, format: &str) -> String {
+    let stem = match path.rfind('.') {
+        Some(i) => &path[..i],
+        None => path,
+    };
+    let extension = if format == "jpeg" {
+        path.rsplit('.').next().unwrap_or("jpg")
+    } else {
+        format
+    };
+
+    return format!("{}-{}w.{}", stem, width, extension);
+}
+
+/// Returns all variants of an image in a given `path` that are generated,
+/// which is one for each of the `IMAGE_WIDTHS` narrower than the image in
+/// each of its `variant_formats`. Images are never upscaled.
+pub fn image_variants(path: &str) -> Vec<ImageVariant> {
+    let config = get_config();
+    let (width, height) = match image_dimensions(path.to_string()) {
+        Some(dimensions) => dimensions,
+        None => return Vec::new(),
+    };
+
+    let mut variants: Vec<ImageVariant> = Vec::new();
+
+    for format in variant_formats(path) {
+        for variant_width in config.image_widths.iter().filter(|w| **w < width) {
+            let variant_height = (height as f64 * *variant_width as f64 / width as f64).round() as u32;
+
+            variants.push(ImageVariant {
+                path: variant_path(path, *variant_width, &format),
+                width: *variant_width,
+                height: variant_height.max(1),
+                format: format.clone(),
+            });
+        }
+    }
+
+    return variants;
+}
+
+/// Returns the `srcset` of an image in a given `path` for a given `format`,
+/// including the image itself when it is in that format.
+fn image_srcset(path: &str, format: &str) -> String {
+    let mut candidates: Vec<String> = image_variants(path)
+        .iter()
+        .filter(|variant| variant.format == format)
+        .map(|variant| format!("{} {}w", variant.path, variant.width))
+        .collect();
+
+    if let (true, Some((width, _))) = (image_format(path) == format, image_dimensions(path.to_string())) {
+        candidates.push(format!("{} {}w", path, width));
+    }
+
+    return candidates.join(", ");
+}
+
+/// Escapes given `value` for use within an HTML attribute.
+fn escape_attr(value: &str) -> String {
+    return value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+}
+
+/// Renders responsive HTML for an image in a given `path`, with given `alt`
+/// text, `sizes` and any `attrs`. The image gets a `srcset` of its variants
+/// along with its width and height, and is wrapped in a `picture` element
+/// with a `source` for each of its other variant formats that it has
+/// variants in. Images that can't be processed are rendered as a plain `img`
+/// element.
+pub fn render_image(path: &str, alt: &str, sizes: &str, attrs: &[(String, String)]) -> String {
+    let attrs: String = attrs
+        .iter()
+        .map(|(name, value)| format!(" {}=\"{}\"", name, escape_attr(value)))
+        .collect();
+
+    if !is_processable_image(path) || image_dimensions(path.to_string()).is_none() {
+        return format!("<img src=\"{}\" alt=\"{}\"{}>", escape_attr(path), escape_attr(alt), attrs);
+    }
+
+    let (width, height) = image_dimensions(path.to_string()).unwrap();
+    let format = image_format(path);
+    let img = format!(
+        "<img src=\"{}\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" alt=\"{}\"{}>",
+        escape_attr(path),
+        image_srcset(path, &format),
+        escape_attr(sizes),
+        width,
+        height,
+        escape_attr(alt),
+        attrs
+    );
+
+    let sources: String = variant_formats(path)
+        .iter()
+        .filter(|f| **f != format)
+        .map(|f| (f, image_srcset(path, f)))
+        .filter(|(_, srcset)| !srcset.is_empty())
+        .map(|(f, srcset)| {
+            format!(
+                "<source type=\"image/{}\" srcset=\"{}\" sizes=\"{}\">",
+                f,
+                srcset,
+                escape_attr(sizes)
+            )
+        })
+        .collect();
+
+    if sources.is_empty() {
+        return img;
+    }
+
+    return format!("<picture>{}{}</picture>", sources, img);
+}
+
+/// Matches the images of rendered Markdown, see `rewrite_images`.
+static IMG_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// Rewrites the images within given `html` of a content file in a given
+/// `path` into responsive images. Relative image sources are resolved
+/// relative to the content file. External images are left as they are.
+pub fn rewrite_images(html: &str, path: &str) -> String {
+    let img_regex = IMG_REGEX
+        .get_or_init(|| Regex::new(r#"<img src="([^"]*)" alt="([^"]*)"( title="[^"]*")? ?/?>"#).unwrap());
+    let relative_dir = Path::new(&path.replace(&get_config().dir, ""))
+        .parent()
+        .map(|p| p.display().to_string())
+        .unwrap_or_default();
+
+    return img_regex
+        .replace_all(html, |captures: &Captures| {
+            let src = &captures[1];
+
+            if src.contains("://") || src.starts_with("//") || src.starts_with("data:") {
+                return captures[0].to_string();
+            }
+
+            let src = if src.starts_with('/') {
+                src.to_string()
+            } else {
+                links::normalize_path(&Path::new(&relative_dir).join(src))
+            };
+
+            let mut attrs = vec![(String::from("loading"), String::from("lazy"))];
+
+            if let Some(title) = captures.get(3) {
+                let title = title.as_str().trim_start_matches(" title=\"").trim_end_matches('"');
+                attrs.push((String::from("title"), title.replace("&quot;", "\"")));
+            }
+
+            return render_image(
+                &src,
+                &captures[2].replace("&quot;", "\""),
+                &get_config().image_sizes,
+                &attrs,
+            );
+        })
+        .to_string();
+}
+
+/// Resizes given `image` into a given `variant` and encodes it in the
+/// variant's format into a given `target` file. JPEG variants are encoded
+/// in the `IMAGE_QUALITY`, WebP variants losslessly.
+fn encode_variant(image: &DynamicImage, variant: &ImageVariant, target: &Path) -> Result<(), String> {
+    let resized = image.resize(variant.width, variant.height, FilterType::Lanczos3);
+    let file = fs::File::create(target).map_err(|e| e.to_string())?;
+    let mut writer = BufWriter::new(file);
+
+    return match variant.format.as_str() {
+        "jpeg" => resized
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut writer, get_config().image_quality))
+            .map_err(|e| e.to_string()),
+        "webp" => resized
+            .to_rgba8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut writer))
+            .map_err(|e| e.to_string()),
+        "png" => resized
+            .write_to(&mut writer, ImageFormat::Png)
+            .map_err(|e| e.to_string()),
+        format => Err(format!("Unsupported image format {}", format)),
+    };
+}
+
+/// Generates the variants of all images of the site and its themes into the
+/// output directory. Generated variants are kept in `.oinky-cache/images`,
+/// keyed by the source image and variant, so that unchanged images are not
+/// processed again between builds.
+pub fn process_images() {
+    let config = get_config();
+
+    if config.image_widths.is_empty() {
+        return;
+    }
+
+    let cache_dir = format!("{}{}", config.dir, "/.oinky-cache/images");
+    let _ = fs::create_dir_all(&cache_dir);
+    let images: Vec<String> = find_site_files(FileType::Asset)
+        .into_iter()
+        .filter(|path| is_processable_image(path))
+        .collect();
+
+    // Let the user know why the WebP variants they asked for are missing
+    if config.image_formats.iter().any(|format| format == "webp")
+        && images.iter().any(|path| image_format(path) == "jpeg")
+    {
+        println!("JPEG images get no WebP variants, as lossless WebP would be larger than them.");
+    }
+
+    images.par_iter().for_each(|source| {
+        let relative_path = relative_path(source);
+        let contents = fs::read(source).unwrap_or_default();
+        let mut image: Option<DynamicImage> = None;
+
+        for variant in image_variants(&relative_path) {
+            let mut hasher = Sha384::new();
+            hasher.update(&contents);
+            hasher.update(format!(
+                "{}x{}.{}@{}",
+                variant.width, variant.height, variant.format, config.image_quality
+            ));
+            let hash: String = hasher.finalize().iter().take(12).map(|b| format!("{:02x}", b)).collect();
+            let cache_path = Path::new(&cache_dir).join(format!("{}.{}", hash, variant.format));
+            let target = format!("{}{}", config.output_dir, variant.path);
+
+            if !cache_path.is_file() {
+                if image.is_none() {
+                    image = image::load_from_memory(&contents).ok();
+                }
+
+                let result = match &image {
+                    Some(image) => encode_variant(image, &variant, &cache_path),
+                    None => Err(String::from("Could not decode image")),
+                };
+
+                if let Err(err) = result {
+                    println!("Could not process image {}: {}", relative_path, err);
+                    let _ = fs::remove_file(&cache_path);
+                    continue;
+                }
+            }
+
+            println!("Processing {}", variant.path);
+
+            if let Some(parent) = Path::new(&target).parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+
+            if fs::copy(&cache_path, &target).is_err() {
+                println!("Could not copy image {}", variant.path);
+            }
+        }
+    });
+}
+
+#[test]
+fn test_variant_path() {
+    assert_eq!("/images/photo-480w.jpg", variant_path("/images/photo.jpg", 480, "jpeg"));
+    assert_eq!("/images/photo-480w.webp", variant_path("/images/photo.jpg", 480, "webp"));
+    assert_eq!("/images/a.b-960w.png", variant_path("/images/a.b.png", 960, "png"));
+}
+
+#[test]
+fn test_render_image() {
+    let site = super::TestSite::new("images");
+    fs::create_dir_all(site.path("blog")).unwrap();
+    DynamicImage::new_rgb8(800, 400).save(site.path("blog/wide.png")).unwrap();
+    DynamicImage::new_rgb8(800, 400).save(site.path("blog/wide.jpg")).unwrap();
+    DynamicImage::new_rgb8(100, 50).save(site.path("blog/small.png")).unwrap();
+    site.configure(|config| {
+        config.image_widths = vec![480];
+        config.image_formats = vec![String::from("webp")];
+    });
+
+    assert_eq!(
+        "<picture><source type=\"image/webp\" srcset=\"/blog/wide-480w.webp 480w\" sizes=\"100vw\">\
+         <img src=\"/blog/wide.png\" srcset=\"/blog/wide-480w.png 480w, /blog/wide.png 800w\" sizes=\"100vw\" \
+         width=\"800\" height=\"400\" alt=\"Wide\"></picture>",
+        render_image("/blog/wide.png", "Wide", "100vw", &[])
+    );
+
+    // Lossy images don't get lossless WebP variants, small images none at all
+    assert_eq!(
+        "<img src=\"/blog/wide.jpg\" srcset=\"/blog/wide-480w.jpg 480w, /blog/wide.jpg 800w\" sizes=\"100vw\" \
+         width=\"800\" height=\"400\" alt=\"\">",
+        render_image("/blog/wide.jpg", "", "100vw", &[])
+    );
+    assert_eq!(
+        "<img src=\"/blog/small.png\" srcset=\"/blog/small.png 100w\" sizes=\"100vw\" \
+         width=\"100\" height=\"50\" alt=\"\">",
+        render_image("/blog/small.png", "", "100vw", &[])
+    );
+    assert_eq!(
+        "<img src=\"/blog/logo.svg\" alt=\"&quot;Logo&quot;\" loading=\"lazy\">",
+        render_image("/blog/logo.svg", "\"Logo\"", "100vw", &[(String::from("loading"), String::from("lazy"))])
+    );
+
+    let post = site.path("blog/post.md");
+    assert_eq!(
+        "<p><img src=\"/blog/small.png\" srcset=\"/blog/small.png 100w\" sizes=\"100vw\" \
+         width=\"100\" height=\"50\" alt=\"Small\" loading=\"lazy\" title=\"Tiny\"></p>",
+        rewrite_images("<p><img src=\"small.png\" alt=\"Small\" title=\"Tiny\" /></p>", &post)
+    );
+    assert_eq!(
+        "<img src=\"/blog/missing.png\" alt=\"\" loading=\"lazy\"> <img src=\"https://example.com/a.png\" alt=\"\" />",
+        rewrite_images("<img src=\"missing.png\" alt=\"\" /> <img src=\"https://example.com/a.png\" alt=\"\" />", &post)
+    );
+}
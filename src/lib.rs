@@ -0,0 +1,2108 @@
+#![allow(clippy::needless_return)]
+
+mod assets;
+mod check;
+mod dsl;
+mod helpers;
+mod i18n;
+mod images;
+mod links;
+mod minify;
+mod site;
+mod utils;
+
+pub use check::CheckIssue;
+pub use dsl::{ContentGroup, TemplateContentDSLItem};
+pub use i18n::ContentTranslation;
+pub use links::ContentBacklink;
+pub use site::{BuildResult, Builder, Site};
+
+use cached::proc_macro::cached;
+use comrak::{markdown_to_html, ComrakOptions};
+use handlebars::Handlebars;
+use hotwatch::{Event, Hotwatch};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::env;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::thread;
+use std::time::Duration;
+use throttle_my_fn::throttle;
+
+#[derive(Clone, Eq, PartialEq, Hash)]
+enum FileType {
+    Handlebars,
+    HandlebarsPages,
+    Markdown,
+    Asset,
+    Sass,
+    Helper,
+}
+
+/// The Handlebars registry shared by all pages, see `registry`.
+static REGISTRY: RwLock<Option<Arc<Handlebars<'static>>>> = RwLock::new(None);
+
+#[derive(Clone)]
+struct TemplatePartial {
+    name: String,
+    path: String,
+}
+
+#[derive(Clone)]
+struct TemplateHelper {
+    name: String,
+    path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TemplateData {
+    site: serde_json::Value,
+    content: HashMap<String, TemplateContentDSLItem>,
+    path: Option<String>,
+    slug: Option<String>,
+    meta: Option<HashMap<String, String>>,
+    entry: Option<String>,
+    time_to_read: Option<usize>,
+    backlinks: Option<Vec<links::ContentBacklink>>,
+    resources: Option<Vec<ContentResource>>,
+    lang: Option<String>,
+    translations: Option<Vec<i18n::ContentTranslation>>,
+    record: Option<serde_json::Value>,
+    prev: Option<ContentItem>,
+    next: Option<ContentItem>,
+    related: Option<Vec<ContentItem>>,
+}
+
+#[derive(Debug, Clone)]
+struct PageJob {
+    label: String,
+    template_path: String,
+    data: TemplateData,
+    write_path: String,
+    minify: bool,
+}
+
+#[derive(Debug, Clone)]
+struct RecordPage {
+    layout: String,
+    slug: String,
+    record: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContentItem {
+    pub path: String,
+    pub slug: String,
+    pub meta: HashMap<String, String>,
+    pub entry: String,
+    pub time_to_read: usize,
+    pub backlinks: Vec<links::ContentBacklink>,
+    pub resources: Vec<ContentResource>,
+    pub lang: String,
+    pub translations: Vec<i18n::ContentTranslation>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentResource {
+    pub name: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Config {
+    dir: String,
+    output_dir: String,
+    timezone: String,
+    locale: Option<String>,
+    strict_links: bool,
+    fingerprint_assets: bool,
+    sass_style: String,
+    production: bool,
+    minify_exclude: Vec<String>,
+    image_widths: Vec<u32>,
+    image_formats: Vec<String>,
+    image_quality: u8,
+    image_sizes: String,
+    image_rewrite_markdown: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    jobs: usize,
+    themes: Vec<String>,
+    languages: Vec<String>,
+}
+
+/// Settings read from the `oinky.toml` file in the root directory.
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    theme: Option<String>,
+    themes: Option<Vec<String>>,
+    languages: Option<Vec<String>>,
+}
+
+/// The config of the site being built by a `Site`, which takes precedence
+/// over the config read from the environment.
+static CONFIG: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+
+/// The config outside of a `Site`, see `get_config`.
+static DEFAULT_CONFIG: OnceLock<Arc<Config>> = OnceLock::new();
+
+/// Held while a `Site` runs, as the config, caches and registry it builds
+/// with are shared by the whole process.
+static BUILD_LOCK: Mutex<()> = Mutex::new(());
+
+/// Errors reported during the current build, see `report_error`.
+static BUILD_ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Records an error `message` of the current build, which fails the build
+/// once it's done.
+fn report_error(message: String) {
+    BUILD_ERRORS.lock().unwrap().push(message);
+}
+
+/// Takes the errors reported since they were last taken.
+fn take_build_errors() -> Vec<String> {
+    return std::mem::take(&mut *BUILD_ERRORS.lock().unwrap());
+}
+
+/// Returns runtime config for Oinky such as the directory
+/// where to run Oinky in. This is the config of the `Site` being built, or
+/// otherwise the config of the `READ_DIR` directory, or the current
+/// directory, read from the environment once. An invalid `oinky.toml` file
+/// is reported by `Builder::build`, so here it's left out.
+fn get_config() -> Arc<Config> {
+    if let Some(config) = CONFIG.read().unwrap().as_ref() {
+        return config.clone();
+    }
+
+    return DEFAULT_CONFIG
+        .get_or_init(|| {
+            let dir = env::var("READ_DIR")
+                .ok()
+                .or(env::current_dir().ok().map(|dir| dir.display().to_string()))
+                .unwrap_or_default();
+
+            Arc::new(load_config(dir.clone()).unwrap_or(config_from_env(dir, ConfigFile::default())))
+        })
+        .clone();
+}
+
+/// Makes a given `config` the config of the site being built. Switching to
+/// the config of another site clears everything derived from the previous
+/// one.
+fn set_config(config: &Config) {
+    let mut current = CONFIG.write().unwrap();
+
+    if current.as_deref() == Some(config) {
+        return;
+    }
+
+    *current = Some(Arc::new(config.clone()));
+    drop(current);
+
+    clear_caches();
+    invalidate_registry();
+}
+
+/// Returns the config of a site in a given root `dir` without reading its
+/// `oinky.toml` file, for tests.
+#[cfg(test)]
+fn test_config(dir: &str) -> Config {
+    return config_from_env(dir.to_string(), ConfigFile::default());
+}
+
+/// Makes a given `config` the config of the site being built until the
+/// returned guard is dropped, the same as a `Site` does, for tests.
+#[cfg(test)]
+fn lock_config(config: Config) -> std::sync::MutexGuard<'static, ()> {
+    let lock = BUILD_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    set_config(&config);
+
+    return lock;
+}
+
+/// A site in a fresh temporary directory for tests, which is the site being
+/// built until it's dropped. The directory is removed on drop, so also when
+/// an assertion fails.
+#[cfg(test)]
+struct TestSite {
+    dir: String,
+    _lock: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl TestSite {
+    fn new(name: &str) -> TestSite {
+        let dir = std::env::temp_dir().join(format!("oinky-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let dir = dir.display().to_string();
+        let lock = lock_config(test_config(&dir));
+
+        return TestSite { dir, _lock: lock };
+    }
+
+    /// Absolute path of a file relative to the site directory.
+    fn path(&self, relative: &str) -> String {
+        return format!("{}/{}", self.dir, relative);
+    }
+
+    /// Writes a file relative to the site directory, creating its parents.
+    fn write(&self, relative: &str, contents: impl AsRef<[u8]>) {
+        let path = self.path(relative);
+        fs::create_dir_all(Path::new(&path).parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Replaces the site config with the test config as changed by `change`.
+    fn configure(&self, change: impl FnOnce(&mut Config)) {
+        let mut config = test_config(&self.dir);
+        change(&mut config);
+        set_config(&config);
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestSite {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Clears the caches of everything derived from the config.
+fn clear_caches() {
+    use cached::Cached;
+
+    IGNORE_MATCHER.lock().unwrap().cache_clear();
+    FIND_SITE_FILES.lock().unwrap().cache_clear();
+    FIND_FILES.lock().unwrap().cache_clear();
+    FIND_PARTIALS.lock().unwrap().cache_clear();
+    FIND_HELPERS.lock().unwrap().cache_clear();
+    PARSE_CONTENT_FILE_ENTRY.lock().unwrap().cache_clear();
+    PARSE_CONTENT_FILES.lock().unwrap().cache_clear();
+    COMPOSE_GLOBAL_TEMPLATE_DATA.lock().unwrap().cache_clear();
+    GET_SITE_INFO.lock().unwrap().cache_clear();
+    assets::MINIFIED_ASSET_CONTENTS.lock().unwrap().cache_clear();
+    assets::ASSET_MANIFEST.lock().unwrap().cache_clear();
+    assets::SASS_OUTPUTS.lock().unwrap().clear();
+    assets::SASS_DEPENDENCIES.lock().unwrap().clear();
+    dsl::GET_DSL_ITEMS.lock().unwrap().cache_clear();
+    dsl::COMPOSE_CONTENT_FROM_DSL.lock().unwrap().cache_clear();
+    dsl::COMPOSE_CONTENT_NAVIGATION.lock().unwrap().cache_clear();
+    i18n::TRANSLATION_INDEX.lock().unwrap().cache_clear();
+    i18n::TRANSLATION_STRINGS.lock().unwrap().cache_clear();
+    images::IMAGE_DIMENSIONS.lock().unwrap().cache_clear();
+    links::CONTENT_LINK_INDEX.lock().unwrap().cache_clear();
+    minify::MINIFY_EXCLUDE_PATTERNS.lock().unwrap().cache_clear();
+}
+
+/// Resolves a given `path` of a directory relative to a given root `dir`,
+/// leaving absolute paths as they are.
+fn resolve_dir(dir: &str, path: &str) -> String {
+    let path = path.trim().trim_end_matches('/');
+
+    if path.starts_with('/') {
+        return path.to_string();
+    }
+
+    return format!("{}/{}", dir, path);
+}
+
+/// Resolves a given `theme` of the site in a given root `dir` into the
+/// directory of the theme. Themes given by name, such as `mini`, are in the
+/// `themes` directory, others are paths relative to the root directory.
+fn resolve_theme_dir(dir: &str, theme: &str) -> String {
+    let theme = theme.trim().trim_end_matches('/');
+
+    if !theme.starts_with('/') && !theme.contains('/') {
+        return format!("{}/themes/{}", dir, theme);
+    }
+
+    return resolve_dir(dir, theme);
+}
+
+/// Reads the config of the site in a given root `dir` from its `oinky.toml`
+/// file and the environment. Not cached, so that every `Site` sees the
+/// current file.
+fn load_config(dir: String) -> Result<Config, String> {
+    let config_file: ConfigFile = match fs::read_to_string(format!("{}{}", dir, "/oinky.toml")) {
+        Ok(contents) => toml::from_str(&contents)
+            .map_err(|err| format!("Something went wrong within your oinky.toml: {}", err))?,
+        Err(_) => ConfigFile::default(),
+    };
+
+    return Ok(config_from_env(dir, config_file));
+}
+
+/// Builds the config of the site in a given root `dir` from the environment
+/// and a given `config_file`.
+fn config_from_env(dir: String, config_file: ConfigFile) -> Config {
+    return Config {
+        dir: dir.clone(),
+        output_dir: resolve_dir(&dir, &env::var("OUTPUT_DIR").unwrap_or(String::from("public"))),
+        timezone: env::var("TIMEZONE")
+            .or(env::var("UTC_OFFSET"))
+            .unwrap_or(String::from("UTC")),
+        locale: env::var("LOCALE").ok().filter(|locale| !locale.is_empty()),
+        strict_links: env::var("STRICT_LINKS").unwrap_or_default() == "true",
+        fingerprint_assets: env::var("FINGERPRINT_ASSETS").unwrap_or_default() == "true",
+        sass_style: env::var("SASS_STYLE").unwrap_or(String::from("expanded")),
+        production: env::var("PRODUCTION").unwrap_or_default() == "true",
+        minify_exclude: env::var("MINIFY_EXCLUDE")
+            .unwrap_or_default()
+            .split(',')
+            .map(|pattern| pattern.trim().to_string())
+            .filter(|pattern| !pattern.is_empty())
+            .collect(),
+        image_widths: env::var("IMAGE_WIDTHS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|width| width.trim().parse::<u32>().ok())
+            .collect(),
+        image_formats: env::var("IMAGE_FORMATS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|format| format.trim().to_lowercase().replace("jpg", "jpeg"))
+            .filter(|format| ["jpeg", "png", "webp"].contains(&format.as_str()))
+            .collect(),
+        image_quality: env::var("IMAGE_QUALITY")
+            .ok()
+            .and_then(|quality| quality.trim().parse::<u8>().ok())
+            .unwrap_or(80)
+            .clamp(1, 100),
+        image_sizes: env::var("IMAGE_SIZES").unwrap_or(String::from("100vw")),
+        image_rewrite_markdown: env::var("IMAGE_REWRITE_MARKDOWN").unwrap_or_default() == "true",
+        include: env::var("INCLUDE")
+            .unwrap_or_default()
+            .split(',')
+            .map(|pattern| pattern.trim().to_string())
+            .filter(|pattern| !pattern.is_empty())
+            .collect(),
+        exclude: env::var("EXCLUDE")
+            .unwrap_or_default()
+            .split(',')
+            .map(|pattern| pattern.trim().to_string())
+            .filter(|pattern| !pattern.is_empty())
+            .collect(),
+        jobs: env::var("JOBS")
+            .ok()
+            .and_then(|jobs| jobs.parse::<usize>().ok())
+            .unwrap_or(0),
+        themes: env::var("THEMES")
+            .map(|themes| themes.split(',').map(String::from).collect())
+            .unwrap_or(config_file.themes.unwrap_or(config_file.theme.into_iter().collect()))
+            .iter()
+            .filter(|theme| !theme.trim().is_empty())
+            .map(|theme| resolve_theme_dir(&dir, theme))
+            .collect(),
+        languages: env::var("LANGUAGES")
+            .map(|languages| languages.split(',').map(String::from).collect())
+            .unwrap_or(config_file.languages.unwrap_or_default())
+            .iter()
+            .map(|lang| lang.trim().to_string())
+            .filter(|lang| !lang.is_empty())
+            .collect(),
+    };
+}
+
+/// Validates a given `config`, returning an error for a timezone or locale
+/// that dates can't be formatted in.
+fn validate_config(config: &Config) -> Result<(), String> {
+    let timezone = utils::Timezone::parse(&config.timezone).ok_or(format!(
+        "Unknown timezone {}, use an IANA name or a UTC offset such as +05:30",
+        config.timezone
+    ))?;
+
+    if let Some(locale) = &config.locale {
+        let now = chrono::Utc::now().fixed_offset();
+
+        utils::format_date(&now, "%B", timezone, Some(locale))
+            .map_err(|err| format!("Invalid LOCALE: {}", err))?;
+    }
+
+    return Ok(());
+}
+
+/// Builds the ignore matcher of the root directory from the `.oinkyignore`
+/// file, which uses the same glob semantics as `.gitignore`, and the
+/// `EXCLUDE` patterns. `INCLUDE` patterns win over both, which makes it
+/// possible to re-include files within an excluded directory.
+#[cached(time = 2)]
+fn ignore_matcher() -> Arc<Gitignore> {
+    let config = get_config();
+    let mut builder = GitignoreBuilder::new(&config.dir);
+    let ignore_file = format!("{}{}", config.dir, "/.oinkyignore");
+
+    if Path::new(&ignore_file).is_file() {
+        if let Some(err) = builder.add(&ignore_file) {
+            println!("Could not read .oinkyignore: {}", err);
+        }
+    }
+
+    let patterns = config
+        .exclude
+        .iter()
+        .cloned()
+        .chain(config.include.iter().map(|pattern| format!("!{}", pattern)));
+
+    for pattern in patterns {
+        if let Err(err) = builder.add_line(None, &pattern) {
+            println!("Could not parse ignore pattern {}: {}", pattern, err);
+        }
+    }
+
+    return Arc::new(builder.build().unwrap_or(Gitignore::empty()));
+}
+
+/// Determines if the given `path`, which is a directory when `is_dir` is
+/// enabled, is ignored by the `.oinkyignore` file or `EXCLUDE` patterns.
+/// Ignored directories are still browsed when an `INCLUDE` pattern could
+/// re-include a file within them.
+fn is_ignored(path: &str, is_dir: bool) -> bool {
+    let config = get_config();
+
+    if !path.starts_with(&config.dir) {
+        return false;
+    }
+
+    let is_ignored = ignore_matcher()
+        .matched_path_or_any_parents(path, is_dir)
+        .is_ignore();
+
+    if is_ignored && is_dir {
+        return !may_include_within(&path.replace(&config.dir, ""));
+    }
+
+    return is_ignored;
+}
+
+/// Determines if an `INCLUDE` pattern could match a path within a directory
+/// in a given `relative_dir`, such as `/drafts`. Patterns with a slash are
+/// relative to the root directory, so they can only match within the
+/// directories on their path, such as `/drafts/keep/**`. Patterns without
+/// one, such as `*.md`, match at any depth.
+fn may_include_within(relative_dir: &str) -> bool {
+    return get_config().include.iter().any(|pattern| {
+        let pattern = pattern.trim_end_matches('/');
+
+        if !pattern.contains('/') {
+            return true;
+        }
+
+        let prefix: Vec<&str> = pattern
+            .trim_start_matches('/')
+            .split('/')
+            .take_while(|segment| !segment.contains(['*', '?', '[', '\\']))
+            .collect();
+        let prefix = format!("/{}", prefix.join("/"));
+
+        return prefix == "/"
+            || prefix == relative_dir
+            || prefix.starts_with(&format!("{}/", relative_dir))
+            || relative_dir.starts_with(&format!("{}/", prefix));
+    });
+}
+
+/// Returns the directories the site is built from, which are the root
+/// directory followed by the theme directories in order of priority.
+fn site_dirs() -> Vec<String> {
+    let config = get_config();
+
+    return std::iter::once(config.dir.clone()).chain(config.themes.iter().cloned()).collect();
+}
+
+/// Returns a given absolute `path` relative to the root directory, or to the
+/// theme directory it's in, such as `/css/style.css`.
+fn relative_path(path: &str) -> String {
+    let config = get_config();
+
+    // Themes may live within the root directory, so they go first
+    for theme in &config.themes {
+        if let Some(relative_path) = path.strip_prefix(theme.as_str()) {
+            if relative_path.starts_with('/') {
+                return relative_path.to_string();
+            }
+        }
+    }
+
+    return path.replace(&config.dir, "");
+}
+
+/// Finds all files that match a `file_type` within the root directory and
+/// the themes. Files of the site take precedence over theme files with the
+/// same relative path, and files of earlier themes over those of later ones.
+#[cached(time = 2)]
+fn find_site_files(file_type: FileType) -> Vec<String> {
+    let mut files: Vec<String> = Vec::new();
+    let mut relative_paths: Vec<String> = Vec::new();
+
+    for dir in site_dirs() {
+        for file in find_files(dir, file_type.clone()) {
+            let relative_path = relative_path(&file);
+
+            if !relative_paths.contains(&relative_path) {
+                relative_paths.push(relative_path);
+                files.push(file);
+            }
+        }
+    }
+
+    return files;
+}
+
+/// Resolves a given `relative_path`, such as `/_layouts/post.hbs`, into the
+/// absolute path of the file within the root directory or the first theme
+/// that has it. Falls back to the path within the root directory.
+fn resolve_site_path(relative_path: &str) -> String {
+    return site_dirs()
+        .iter()
+        .map(|dir| format!("{}{}", dir, relative_path))
+        .find(|path| Path::new(path).is_file())
+        .unwrap_or(format!("{}{}", get_config().dir, relative_path));
+}
+
+/// Determines if the given `path` matches a Handlebars file.
+fn is_handlebars_file(path: &str) -> bool {
+    let relative_path = relative_path(path);
+
+    return !relative_path.starts_with("/public")
+        && !relative_path.starts_with("/node_modules")
+        && (path.ends_with(".hbs") || path.ends_with(".handlebars"));
+}
+
+/// Determines if the given `path` matches a Handlebars Page file.
+fn is_handlebars_page_file(path: &str) -> bool {
+    let relative_path = relative_path(path);
+
+    return !relative_path.starts_with("/_layouts")
+        && !relative_path.starts_with("/_partials")
+        && !relative_path.starts_with("/public")
+        && !relative_path.starts_with("/node_modules")
+        && (path.ends_with(".hbs") || path.ends_with(".handlebars"));
+}
+
+/// Determines if the given `path` matches a Markdown file.
+fn is_markdown_file(path: &str) -> bool {
+    let relative_path = relative_path(path);
+
+    return !relative_path.starts_with("/_layouts")
+        && !relative_path.starts_with("/_partials")
+        && !relative_path.starts_with("/public")
+        && !relative_path.starts_with("/node_modules")
+        && (path.ends_with(".md") || path.ends_with(".markdown"));
+}
+
+/// Determines if the given `path` matches a data file.
+fn is_data_file(path: &str) -> bool {
+    let relative_path = relative_path(path);
+
+    return relative_path == "/site.json"
+        || relative_path == "/content.json"
+        || (relative_path.starts_with("/_i18n/") && relative_path.ends_with(".yaml"))
+        || dsl::get_dsl_items()
+            .iter()
+            .any(|item| dsl::is_data_source(&item.from) && format!("/{}", item.from) == relative_path);
+}
+
+/// Determines if the given `path` matches a Sass/SCSS file, including
+/// partials.
+fn is_sass_file(path: &str) -> bool {
+    let relative_path = relative_path(path);
+
+    return !relative_path.starts_with("/_layouts")
+        && !relative_path.starts_with("/_partials")
+        && !relative_path.starts_with("/public")
+        && !relative_path.starts_with("/node_modules")
+        && !relative_path.starts_with("/.")
+        && (path.ends_with(".scss") || path.ends_with(".sass"));
+}
+
+/// Determines if the given `path` matches a Sass/SCSS entry point, which is
+/// any Sass/SCSS file that is not a partial starting with an underscore.
+fn is_sass_entry_file(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or_default();
+
+    return is_sass_file(path) && !file_name.starts_with('_');
+}
+
+/// Determines if the given `path` matches a Rhai script helper file within
+/// the /_helpers directory.
+fn is_helper_file(path: &str) -> bool {
+    return relative_path(path).starts_with("/_helpers/") && path.ends_with(".rhai");
+}
+
+/// Determines if the given `path` matches a asset file.
+fn is_asset_file(path: &str) -> bool {
+    let relative_path = relative_path(path);
+
+    return !path.ends_with(".hbs")
+        && !path.ends_with(".handlebars")
+        && !path.ends_with(".md")
+        && !path.ends_with(".markdown")
+        && !path.ends_with(".scss")
+        && !path.ends_with(".sass")
+        && !is_data_file(path)
+        && relative_path != "/oinky.toml"
+        && !relative_path.starts_with("/_helpers")
+        && !relative_path.starts_with("/_layouts")
+        && !relative_path.starts_with("/_partials")
+        && !relative_path.starts_with("/public")
+        && !relative_path.starts_with("/node_modules")
+        && !relative_path.starts_with("/.");
+}
+
+/// Recursively browses directories within the given `dir` for any and all
+/// files that match a `file_type`. Returns a vector of strings where each
+/// string is an absolute path to the file, sorted by path.
+#[cached(time = 2)]
+fn find_files(dir: String, file_type: FileType) -> Vec<String> {
+    let config = get_config();
+    let mut files: Vec<String> = Vec::new();
+    let read_dir = fs::read_dir(dir);
+
+    if read_dir.is_err() {
+        return Vec::new();
+    }
+
+    for entry in read_dir.unwrap() {
+        let path = entry.unwrap().path();
+        let path_str = path.as_path().display().to_string();
+
+        // Configured themes are only browsed on their own, and output never is
+        if is_ignored(&path_str, path.is_dir())
+            || config.themes.contains(&path_str)
+            || path_str == config.output_dir
+        {
+            continue;
+        }
+
+        if path.is_dir() {
+            files.extend(find_files(path_str.clone(), file_type.clone()));
+        } else {
+            match file_type {
+                FileType::Handlebars => {
+                    if is_handlebars_file(&path_str) {
+                        files.push(path_str);
+                    }
+                }
+                FileType::HandlebarsPages => {
+                    if is_handlebars_page_file(&path_str) {
+                        files.push(path_str);
+                    }
+                }
+                FileType::Markdown => {
+                    if is_markdown_file(&path_str) {
+                        files.push(path_str);
+                    }
+                }
+                FileType::Asset => {
+                    if is_asset_file(&path_str) {
+                        files.push(path_str);
+                    }
+                }
+                FileType::Sass => {
+                    if is_sass_entry_file(&path_str) {
+                        files.push(path_str);
+                    }
+                }
+                FileType::Helper => {
+                    if is_helper_file(&path_str) {
+                        files.push(path_str);
+                    }
+                }
+            }
+        }
+    }
+
+    files.sort();
+
+    return files;
+}
+
+/// Returns the name of a partial in a given `path` relative to a /_partials
+/// directory, which is the path without extension, such as `blog/card`.
+fn partial_name(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+
+    return path
+        .strip_suffix(".hbs")
+        .or_else(|| path.strip_suffix(".handlebars"))
+        .unwrap_or(path)
+        .to_string();
+}
+
+/// Finds all partials from within the /_partials directory of the root
+/// directory and of each theme, which it turns into a vector of consumable
+/// `TemplatePartial`'s. Consumed by Handlebars in `build_registry`. Partials
+/// of the site take precedence over those of themes, and partials of
+/// earlier themes over those of later ones. Returns an error when two
+/// partials within the same directory have the same name.
+#[cached(time = 2)]
+fn find_partials() -> Result<Vec<TemplatePartial>, String> {
+    let mut partials: Vec<TemplatePartial> = Vec::new();
+
+    for dir in site_dirs() {
+        let partials_dir = format!("{}{}", dir, "/_partials");
+        let mut names: HashMap<String, String> = HashMap::new();
+
+        for path in find_files(partials_dir.clone(), FileType::Handlebars) {
+            let name = partial_name(&path.replace(&partials_dir, ""));
+
+            if let Some(other_path) = names.insert(name.clone(), path.clone()) {
+                return Err(format!(
+                    "Partials {} and {} are both named {}",
+                    other_path, path, name
+                ));
+            }
+
+            if !partials.iter().any(|partial| partial.name == name) {
+                partials.push(TemplatePartial { name, path });
+            }
+        }
+    }
+
+    return Ok(partials);
+}
+
+/// Finds all Rhai script helpers from within the /_helpers directory of the
+/// root directory and of each theme, named after their file name, such as
+/// `pluralize` for `/_helpers/text/pluralize.rhai`. Helpers of the site take
+/// precedence over those of themes, and helpers of earlier themes over
+/// those of later ones. Returns an error when two helpers within the same
+/// directory have the same name.
+#[cached(time = 2)]
+fn find_helpers() -> Result<Vec<TemplateHelper>, String> {
+    let mut helpers: Vec<TemplateHelper> = Vec::new();
+
+    for dir in site_dirs() {
+        let helpers_dir = format!("{}{}", dir, "/_helpers");
+        let mut names: HashMap<String, String> = HashMap::new();
+
+        for path in find_files(helpers_dir, FileType::Helper) {
+            let name = Path::new(&path)
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+
+            if let Some(other_path) = names.insert(name.clone(), path.clone()) {
+                return Err(format!(
+                    "Helpers {} and {} are both named {}",
+                    other_path, path, name
+                ));
+            }
+
+            if !helpers.iter().any(|helper| helper.name == name) {
+                helpers.push(TemplateHelper { name, path });
+            }
+        }
+    }
+
+    return Ok(helpers);
+}
+
+/// Parses a given content item's `contents` for YAML-like meta-data which it
+/// then returns as a key-value HashMap, or an error naming a line of the
+/// meta-data that isn't a `key: value` pair.
+#[cached(time = 2)]
+fn parse_content_file_meta(contents: String) -> Result<HashMap<String, String>, String> {
+    let regex = Regex::new(r"(?s)^(---)(.*?)(---|\.\.\.)").unwrap();
+
+    if regex.find(&contents).is_none() {
+        return Ok(HashMap::new());
+    }
+
+    let meta_block = regex.find(&contents).unwrap().as_str();
+    let meta_lines = meta_block.lines();
+    let mut meta: HashMap<String, String> = HashMap::new();
+
+    for line in meta_lines {
+        if line == "---" || line == "..." || line.trim().is_empty() {
+            continue;
+        }
+
+        let (key, val) = line
+            .split_once(':')
+            .ok_or(format!("Invalid front matter line \"{}\", expected key: value", line))?;
+
+        meta.insert(key.trim().to_string(), val.trim().to_string());
+    }
+
+    return Ok(meta);
+}
+
+/// Parses a given content item's `contents` for the Markdown entry which it
+/// then returns as a consumable HTML string. Links to other content files
+/// within the entry are resolved relative to the item's `path`.
+#[cached(time = 2)]
+fn parse_content_file_entry(path: String, contents: String) -> String {
+    let regex = Regex::new(r"(?s)^---(.*?)---*").unwrap();
+    let entry = regex.replace(&contents, "");
+    let entry = links::resolve_links(&path, &entry);
+    let mut opts = ComrakOptions::default();
+    opts.render.unsafe_ = true;
+
+    let html = markdown_to_html(&entry, &opts);
+
+    if get_config().image_rewrite_markdown && !get_config().image_widths.is_empty() {
+        return images::rewrite_images(&html, &path);
+    }
+
+    return html;
+}
+
+/// Returns the slug of a content item in a given `path`, which is its path
+/// relative to the root directory without the Markdown extension. The slug of
+/// a page bundle is the path of its directory. Content in other than the
+/// default language is prefixed with its language, such as `/et/blog/post`
+/// for `/blog/post.et.md` or `/et/blog/post.md`.
+fn content_slug(path: &str) -> String {
+    let (lang, relative_path) = i18n::split_language(&path.replace(&get_config().dir, ""));
+
+    if is_bundle_index_file(path) {
+        let slug = Path::new(&relative_path).parent().unwrap().display().to_string();
+
+        return i18n::localized_slug(&lang, &slug);
+    }
+
+    let slug = relative_path
+        .strip_suffix(".markdown")
+        .or_else(|| relative_path.strip_suffix(".md"))
+        .unwrap_or(&relative_path);
+
+    return i18n::localized_slug(&lang, slug);
+}
+
+/// Returns the slug of a Handlebars page in a given `path`, which is its
+/// path relative to the root directory without the Handlebars extension,
+/// prefixed with its language like the slugs of content items.
+fn template_slug(path: &str) -> String {
+    let (lang, relative_path) = i18n::split_language(&path.replace(&get_config().dir, ""));
+
+    return i18n::localized_slug(&lang, &relative_path.replace(".hbs", ""));
+}
+
+/// Determines if the given `path` is the `index.md` of a directory, or a
+/// translation of it such as `index.et.md`.
+fn is_index_file(path: &str) -> bool {
+    let (_, relative_path) = i18n::split_language(&path.replace(&get_config().dir, ""));
+    let file_name = relative_path.rsplit('/').next().unwrap_or_default();
+
+    return file_name == "index.md" || file_name == "index.markdown";
+}
+
+/// Determines if the given `path` matches the `index.md` of a page bundle,
+/// which is a directory holding a content file along with its resources,
+/// such as `index.md` or `index.et.md`. Directories holding other content
+/// files, such as `/blog` with its posts, are not page bundles, and neither
+/// is the root directory.
+fn is_bundle_index_file(path: &str) -> bool {
+    let bundle_dir = Path::new(path).parent().unwrap_or(Path::new("")).display().to_string();
+
+    if !is_index_file(path) || bundle_dir == get_config().dir {
+        return false;
+    }
+
+    return find_files(bundle_dir.clone(), FileType::Markdown)
+        .iter()
+        .all(|file| is_index_file(file) && Path::new(file).parent() == Some(Path::new(&bundle_dir)));
+}
+
+/// Returns the resources of a page bundle whose `index.md` is in a given
+/// `path`, which are the asset files next to it in the bundle directory.
+/// They are copied along with the other assets, which puts them next to the
+/// bundle's page so that relative links to them keep working.
+fn find_bundle_resources(path: &str) -> Vec<ContentResource> {
+    if !is_bundle_index_file(path) {
+        return Vec::new();
+    }
+
+    let config = get_config();
+    let bundle_dir = Path::new(path).parent().unwrap();
+
+    let mut resources: Vec<ContentResource> = find_files(bundle_dir.display().to_string(), FileType::Asset)
+        .iter()
+        .filter(|asset| Path::new(asset).parent() == Some(bundle_dir))
+        .map(|asset| ContentResource {
+            name: Path::new(asset).file_name().unwrap().to_string_lossy().to_string(),
+            path: asset.replace(&config.dir, ""),
+        })
+        .collect();
+
+    resources.sort_by(|a, b| a.name.cmp(&b.name));
+
+    return resources;
+}
+
+/// Returns the copies of page bundle resources that translations of the
+/// bundle need, as the source path and the path relative to the output
+/// directory. A translated bundle, such as `/blog/trip/index.et.md`, is
+/// written to its localized slug, such as `/et/blog/trip`, so its resources
+/// are copied there as well for relative links to them to keep working.
+fn localized_bundle_resources() -> Vec<(String, String)> {
+    let config = get_config();
+    let mut copies: Vec<(String, String)> = Vec::new();
+
+    for file in find_files(config.dir.clone(), FileType::Markdown) {
+        if !is_bundle_index_file(&file) {
+            continue;
+        }
+
+        let slug = content_slug(&file);
+        let bundle_dir = Path::new(&file).parent().unwrap().display().to_string();
+
+        if format!("{}{}", config.dir, slug) == bundle_dir {
+            continue;
+        }
+
+        for resource in find_bundle_resources(&file) {
+            copies.push((
+                format!("{}{}", config.dir, resource.path),
+                format!("{}/{}", slug, resource.name),
+            ));
+        }
+    }
+
+    return copies;
+}
+
+/// Parses given Markdown `files` for contents that contain YAML-like meta-data
+/// and the Markdown entry. Returns a vector of `ContentItem`.
+#[cached(time = 2)]
+fn parse_content_files(files: Vec<String>) -> Vec<ContentItem> {
+    return files
+        .par_iter()
+        .filter_map(|file| {
+            let contents = match fs::read_to_string(file) {
+                Ok(contents) => contents,
+                Err(err) => {
+                    report_error(format!("Could not read {}: {}", relative_path(file), err));
+                    return None;
+                }
+            };
+
+            let meta = match parse_content_file_meta(contents.clone()) {
+                Ok(meta) => meta,
+                Err(err) => {
+                    report_error(format!("{}: {}", relative_path(file), err));
+                    return None;
+                }
+            };
+            let entry = parse_content_file_entry(file.clone(), contents);
+            let slug = content_slug(file);
+            let time_to_read = entry.split_whitespace().count() / 225;
+            let backlinks = links::content_link_index()
+                .backlinks
+                .get(file)
+                .cloned()
+                .unwrap_or_default();
+            let resources = find_bundle_resources(file);
+            let (lang, _) = i18n::split_language(&file.replace(&get_config().dir, ""));
+            let translations = i18n::find_translations(file);
+
+            return Some(ContentItem {
+                path: file.clone(),
+                slug,
+                meta,
+                entry,
+                time_to_read,
+                backlinks,
+                resources,
+                lang,
+                translations,
+            });
+        })
+        .collect();
+}
+
+/// Matches the front matter of a Handlebars template.
+static TEMPLATE_FRONT_MATTER_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// Matches the inline partials of a Handlebars template, which fill the
+/// blocks of the layout it extends.
+static INLINE_PARTIAL_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// Reads the source of a Handlebars template in a given `path`, without its
+/// front matter. A template can extend a layout by declaring it in its front
+/// matter, such as `extends: base`, in which case it's wrapped in a partial
+/// block of that layout, which is registered on `hbs` as `_layouts/base`
+/// along with the layouts it extends in turn. The layout renders the
+/// template with `{{> @partial-block}}`, and the template fills named blocks
+/// of the layout, such as `{{#> title}}Default{{/title}}`, with inline
+/// partials, such as `{{#*inline "title"}}About{{/inline}}`. The inline
+/// partials of the templates extending this one are given as `blocks`, and
+/// are repeated after its own so that the most specific template wins. The
+/// `extended` layouts are tracked to catch cycles.
+fn read_template_source(
+    hbs: &mut Handlebars,
+    path: &str,
+    extended: &mut Vec<String>,
+    blocks: &str,
+) -> Result<String, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let meta = parse_content_file_meta(contents.clone()).map_err(|e| format!("{}: {}", path, e))?;
+    let body = TEMPLATE_FRONT_MATTER_REGEX
+        .get_or_init(|| Regex::new(r"(?s)^---(.*?)---\r?\n?").unwrap())
+        .replace(&contents, "")
+        .to_string();
+
+    let layout = match meta.get("extends") {
+        Some(layout) => layout.clone(),
+        None => return Ok(body),
+    };
+
+    if extended.contains(&layout) {
+        return Err(format!("{}: layout {} extends itself", path, layout));
+    }
+
+    extended.push(layout.clone());
+
+    let inline_regex = INLINE_PARTIAL_REGEX.get_or_init(|| {
+        Regex::new(r#"(?s)\{\{~?#\*inline\s+"[^"]*"\s*~?\}\}.*?\{\{~?/inline\s*~?\}\}"#).unwrap()
+    });
+    let own_blocks: String = inline_regex.find_iter(&body).map(|m| m.as_str()).collect();
+    let layout_path = resolve_site_path(&format!("{}{}{}", "/_layouts/", layout, ".hbs"));
+    let layout_source = read_template_source(
+        hbs,
+        &layout_path,
+        extended,
+        &format!("{}{}", own_blocks, blocks),
+    )?;
+    let layout_name = format!("_layouts/{}", layout);
+
+    // Layouts that carry blocks of the templates extending them are
+    // registered under a name of their own, as the registry is shared.
+    let layout_name = if blocks.is_empty() && own_blocks.is_empty() {
+        layout_name
+    } else {
+        let mut hasher = DefaultHasher::new();
+        format!("{}{}", own_blocks, blocks).hash(&mut hasher);
+        format!("{}-{:x}", layout_name, hasher.finish())
+    };
+
+    hbs.register_partial(&layout_name, layout_source)
+        .map_err(|e| format!("{}: {}", layout_path, e))?;
+
+    return Ok(format!("{{{{#> {0}}}}}{1}{2}{{{{/{0}}}}}", layout_name, body, blocks));
+}
+
+/// Registers the helpers available to all templates on given `hbs`.
+fn register_helpers(hbs: &mut Handlebars) {
+    hbs.register_helper("date", Box::new(helpers::date_helper));
+    hbs.register_helper("format_date", Box::new(helpers::format_date_helper));
+    hbs.register_helper("relative_time", Box::new(helpers::relative_time_helper));
+    hbs.register_helper("is_slug", Box::new(helpers::is_slug_helper));
+    hbs.register_helper("unless_slug", Box::new(helpers::unless_slug_helper));
+    hbs.register_helper("is_section", Box::new(helpers::is_section_helper));
+    hbs.register_helper("active_class", Box::new(helpers::active_class_helper));
+    hbs.register_helper("asset", Box::new(helpers::asset_helper));
+    hbs.register_helper("asset_integrity", Box::new(helpers::asset_integrity_helper));
+    hbs.register_helper("image", Box::new(helpers::image_helper));
+    hbs.register_helper("t", Box::new(helpers::t_helper));
+    helpers::register_standard_helpers(hbs);
+}
+
+/// Builds a Handlebars registry with all helpers, the given `partials` and
+/// the templates in given `template_paths`, which are registered by their
+/// path along with the layouts they extend. Script helpers are Rhai files
+/// within the /_helpers directory of the site or its themes, named by their
+/// file name, such as `pluralize` for `_helpers/pluralize.rhai`. Scripts get
+/// the helper's `params` array and `hash` map, and return the output.
+fn build_registry(
+    template_paths: Vec<String>,
+    partials: Vec<TemplatePartial>,
+) -> Result<Handlebars<'static>, String> {
+    let mut hbs = Handlebars::new();
+
+    register_helpers(&mut hbs);
+
+    // Register script helpers, which may override built-in ones
+    for helper in find_helpers()? {
+        hbs.register_script_helper_file(&helper.name, &helper.path)
+            .map_err(|e| format!("Something went wrong within your helper, {}: {}", helper.path, e))?;
+    }
+
+    for partial in partials {
+        hbs.register_template_file(&partial.name, &partial.path)
+            .map_err(|e| format!("Something went wrong within your partial, {}: {}", partial.name, e))?;
+    }
+
+    for template_path in template_paths {
+        let source = read_template_source(&mut hbs, &template_path, &mut Vec::new(), "")
+            .map_err(|e| format!("Something went wrong within your template, {}", e))?;
+
+        hbs.register_template_string(&template_path, source)
+            .map_err(|e| format!("Something went wrong within your template, {}: {}", template_path, e))?;
+    }
+
+    return Ok(hbs);
+}
+
+/// Returns the Handlebars registry shared by all pages, which holds every
+/// layout, page and partial within the root directory. It's built on first
+/// use and kept until `invalidate_registry` is called.
+fn registry() -> Result<Arc<Handlebars<'static>>, String> {
+    if let Some(registry) = REGISTRY.read().unwrap().as_ref() {
+        return Ok(registry.clone());
+    }
+
+    let mut registry = REGISTRY.write().unwrap();
+
+    if registry.is_none() {
+        let template_paths: Vec<String> = site_dirs()
+            .into_iter()
+            .flat_map(|dir| find_files(dir, FileType::Handlebars))
+            .filter(|path| !relative_path(path).starts_with("/_partials"))
+            .collect();
+
+        *registry = Some(Arc::new(build_registry(template_paths, find_partials()?)?));
+    }
+
+    return Ok(registry.clone().unwrap());
+}
+
+/// Invalidates the shared Handlebars registry, so that it's built anew from
+/// the current layouts, pages and partials on next use.
+fn invalidate_registry() {
+    *REGISTRY.write().unwrap() = None;
+}
+
+/// Builds HTML from a Handlebars template in a path `template_path` by fusing
+/// together `data`, using the shared registry. Returns a HTML string, or an
+/// error when the template can't be rendered.
+fn build_html(template_path: String, data: TemplateData) -> Result<String, String> {
+    return registry()?
+        .render(&template_path, &data)
+        .map_err(|err| format!("Something went wrong within your template, {}: {}", template_path, err));
+}
+
+/// Renders a page for each of the given `items` for which `page` returns a
+/// `PageJob`, and writes them to the output directory. Pages are rendered in
+/// parallel on the rayon pool, which is bounded by `JOBS`. Progress is
+/// logged in the order of the items, and errors of any of the pages,
+/// including panics, are reported, failing the build. Returns the paths of
+/// the pages written.
+fn render_pages<T: Sync>(items: &[T], page: impl Fn(&T) -> Option<PageJob> + Sync) -> Vec<String> {
+    // Templates that fail to compile fail every page, so report them once
+    if let Err(err) = registry() {
+        report_error(err);
+        return Vec::new();
+    }
+
+    let results: Vec<Option<(String, Result<String, String>)>> = items
+        .par_iter()
+        .map(|item| {
+            let job = page(item)?;
+            let label = job.label.clone();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let html = build_html(job.template_path, job.data)?;
+                write_page(&job.write_path, html, job.minify);
+
+                return Ok(job.write_path);
+            }))
+            .unwrap_or_else(|err| {
+                let message = err
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or(err.downcast_ref::<&str>().map(|m| m.to_string()))
+                    .unwrap_or_default();
+
+                Err(format!("Building {} panicked: {}", label, message))
+            });
+
+            return Some((label, result));
+        })
+        .collect();
+
+    let mut pages: Vec<String> = Vec::new();
+
+    for (label, result) in results.into_iter().flatten() {
+        println!("Building {}", label);
+
+        match result {
+            Ok(page) => pages.push(page),
+            Err(err) => report_error(err),
+        }
+    }
+
+    return pages;
+}
+
+/// Deletes all files and directories from within the output directory.
+fn empty_public_dir() {
+    let path = &get_config().output_dir;
+
+    if fs::read_dir(path).is_err() {
+        return;
+    }
+
+    for entry in fs::read_dir(path).unwrap() {
+        let file = entry.unwrap();
+        let file_path_str = file.path().as_path().display().to_string();
+
+        if file.path().is_dir() {
+            let remove_dir = fs::remove_dir_all(file.path());
+
+            if remove_dir.is_err() {
+                report_error(format!("Could not remove dir {}", file_path_str));
+            }
+        } else {
+            let remove_file = fs::remove_file(file.path());
+
+            if remove_file.is_err() {
+                report_error(format!("Could not remove file {}", file_path_str));
+            }
+        }
+    }
+}
+
+/// Writes given `contents` into given `path. Parent directories do not have
+/// to exist as they will also be created if they don't.
+fn write_to_path(path: &str, contents: String) {
+    let path = Path::new(&path);
+    let prefix = path.parent().unwrap();
+    fs::create_dir_all(prefix).unwrap();
+
+    let file = fs::File::create(path).unwrap();
+    let mut file = BufWriter::new(file);
+    file.write_all(contents.as_bytes()).unwrap();
+}
+
+/// Writes given `html` of a page into given `path` within the output
+/// directory. HTML pages are minified first in production mode, unless
+/// `minify` is disabled for the page or its path is excluded from it.
+fn write_page(path: &str, html: String, minify: bool) {
+    let is_html = path.ends_with(".html") || path.ends_with(".htm");
+    let html = if minify && is_html && minify::should_minify(path) {
+        minify::minify_html(html)
+    } else {
+        html
+    };
+
+    write_to_path(&format!("{}{}", get_config().output_dir, path), html);
+}
+
+/// Returns the job of rendering the page of a given `content_item` with
+/// given global Handlebars `data` and content `navigation`. Content items
+/// without a layout have no page.
+fn content_page_job(
+    content_item: &ContentItem,
+    data: &TemplateData,
+    navigation: &HashMap<String, dsl::ContentNavigation>,
+) -> Option<PageJob> {
+    let layout = content_item.meta.get("layout")?;
+    let item_navigation = navigation
+        .get(&content_item.path)
+        .cloned()
+        .unwrap_or_default();
+
+    let item_data = TemplateData {
+        path: Some(content_item.path.clone()),
+        slug: Some(content_item.slug.clone()),
+        meta: Some(content_item.meta.clone()),
+        entry: Some(content_item.entry.clone()),
+        time_to_read: Some(content_item.time_to_read),
+        backlinks: Some(content_item.backlinks.clone()),
+        resources: Some(content_item.resources.clone()),
+        lang: Some(content_item.lang.clone()),
+        translations: Some(content_item.translations.clone()),
+        prev: item_navigation.prev,
+        next: item_navigation.next,
+        related: Some(item_navigation.related),
+        ..data.clone()
+    };
+
+    return Some(PageJob {
+        label: content_item.slug.clone(),
+        template_path: resolve_site_path(&format!("{}{}{}", "/_layouts/", layout, ".hbs")),
+        data: item_data,
+        write_path: format!("{}{}", content_item.slug, "/index.html"),
+        minify: content_item.meta.get("minify").map(|m| m != "false").unwrap_or(true),
+    });
+}
+
+/// Compiles all content items within the root directory with given
+/// global Handlebars `data`, resulting in HTML files written to disk.
+/// Returns the paths of the pages written.
+fn compile_content_items(data: TemplateData) -> Vec<String> {
+    let content_files = find_files(get_config().dir.clone(), FileType::Markdown);
+    let content_items = parse_content_files(content_files);
+    let navigation = dsl::compose_content_navigation();
+
+    return render_pages(&content_items, |content_item| {
+        return content_page_job(content_item, &data, &navigation);
+    });
+}
+
+/// Builds the slug of a record page from the given `slug_template`, which is
+/// a Handlebars template rendered with the `record` fields, such as
+/// `/products/{{id}}`. Fields are not HTML-escaped, and slugs that are empty
+/// or would lead out of the output directory are an error.
+fn build_record_slug(slug_template: &str, record: &serde_json::Value) -> Result<String, String> {
+    let mut hbs = Handlebars::new();
+    hbs.register_escape_fn(handlebars::no_escape);
+
+    let slug = hbs.render_template(slug_template, record).map_err(|err| {
+        format!(
+            "Something went wrong within your slug template, {}: {:?}",
+            slug_template, err
+        )
+    })?;
+
+    let segments: Vec<&str> = slug
+        .trim()
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect();
+
+    if segments.is_empty() || segments.contains(&"..") {
+        return Err(format!(
+            "Slug template {} built an invalid slug {:?} for the record {}",
+            slug_template, slug, record
+        ));
+    }
+
+    return Ok(format!("/{}", segments.join("/")));
+}
+
+/// Finds all record pages declared by the `content.json` DSL. Every record of
+/// a dataset that has `pages` becomes a page of its own. Records whose slug
+/// is invalid or already taken by another record, a content file or a
+/// Handlebars page are reported.
+fn find_record_pages(data: &TemplateData) -> Vec<RecordPage> {
+    let mut record_pages: Vec<RecordPage> = Vec::new();
+    let mut slugs: HashMap<String, String> = HashMap::new();
+
+    // Pages are told apart by the path they're written to
+    let dir = get_config().dir.clone();
+    let content_pages = find_files(dir.clone(), FileType::Markdown)
+        .into_iter()
+        .map(|file| (format!("{}/index.html", content_slug(&file)), relative_path(&file)));
+    let template_pages = find_files(dir, FileType::HandlebarsPages)
+        .into_iter()
+        .map(|file| (template_slug(&file), relative_path(&file)));
+    let page_files: HashMap<String, String> = content_pages.chain(template_pages).collect();
+
+    for dsl_item in dsl::get_dsl_items() {
+        let pages = match dsl_item.pages {
+            Some(pages) => pages,
+            None => continue,
+        };
+
+        let value = match data.content.get(&dsl_item.name) {
+            Some(TemplateContentDSLItem::Pulled(value)) => value,
+            _ => {
+                println!("Dataset {} has no records to build pages from.", dsl_item.name);
+                continue;
+            }
+        };
+
+        for record in dsl::get_records(value, pages.records.clone()) {
+            let slug = match build_record_slug(&pages.slug, &record) {
+                Ok(slug) => slug,
+                Err(err) => {
+                    report_error(err);
+                    continue;
+                }
+            };
+
+            if let Some(name) = slugs.get(&slug) {
+                report_error(format!(
+                    "Records of {} and {} have the same slug {}",
+                    name, dsl_item.name, slug
+                ));
+                continue;
+            }
+
+            if let Some(file) = page_files.get(&format!("{}/index.html", slug)) {
+                report_error(format!(
+                    "A record of {} has the same slug {} as {}",
+                    dsl_item.name, slug, file
+                ));
+                continue;
+            }
+
+            slugs.insert(slug.clone(), dsl_item.name.clone());
+            record_pages.push(RecordPage {
+                layout: pages.layout.clone(),
+                slug,
+                record,
+            });
+        }
+    }
+
+    return record_pages;
+}
+
+/// Compiles a page for every record of the datasets that declare `pages`
+/// in the `content.json` DSL with given global Handlebars `data`, resulting
+/// in HTML files written to disk. Returns the paths of the pages written.
+fn compile_record_pages(data: TemplateData) -> Vec<String> {
+    let record_pages = find_record_pages(&data);
+
+    return render_pages(&record_pages, |record_page| {
+        let item_data = TemplateData {
+            slug: Some(record_page.slug.clone()),
+            lang: Some(i18n::default_language()),
+            record: Some(record_page.record.clone()),
+            ..data.clone()
+        };
+
+        return Some(PageJob {
+            label: record_page.slug.clone(),
+            template_path: resolve_site_path(&format!(
+                "{}{}{}",
+                "/_layouts/", record_page.layout, ".hbs"
+            )),
+            data: item_data,
+            write_path: format!("{}{}", record_page.slug, "/index.html"),
+            minify: true,
+        });
+    });
+}
+
+/// Returns the job of rendering the Handlebars page in a given `file` with
+/// given global Handlebars `data`.
+fn template_page_job(file: &str, data: &TemplateData) -> PageJob {
+    let slug = template_slug(file);
+    let (lang, _) = i18n::split_language(&file.replace(&get_config().dir, ""));
+
+    let template_data = TemplateData {
+        slug: Some(slug.clone()),
+        lang: Some(lang),
+        translations: Some(i18n::find_translations(file)),
+        ..data.clone()
+    };
+
+    return PageJob {
+        label: slug.clone(),
+        template_path: file.to_string(),
+        data: template_data,
+        write_path: slug,
+        minify: true,
+    };
+}
+
+/// Compiles all non-layout and non-partial template items within the
+/// root directory with given Handlebars `data`, resulting in HTML files
+/// written to disk. Returns the paths of the pages written.
+fn compile_template_items(data: TemplateData) -> Vec<String> {
+    let template_files = find_files(get_config().dir.clone(), FileType::HandlebarsPages);
+
+    return render_pages(&template_files, |file| {
+        return Some(template_page_job(file, &data));
+    });
+}
+
+/// Renders the page of a content file or Handlebars page in a given `path`
+/// into HTML, without writing it to disk.
+fn render_page(path: &str) -> Result<String, String> {
+    let data = compose_global_template_data();
+
+    let job = if is_markdown_file(path) {
+        let content_item = parse_content_files(vec![path.to_string()])
+            .pop()
+            .ok_or(format!("Could not read {}", path))?;
+
+        content_page_job(&content_item, &data, &dsl::compose_content_navigation())
+            .ok_or(format!("{} has no layout", path))?
+    } else if is_handlebars_page_file(path) {
+        template_page_job(path, &data)
+    } else {
+        return Err(format!("{} is not a page", path));
+    };
+
+    return build_html(job.template_path, job.data);
+}
+
+/// Composes global template data for consumption by Handlebars templates.
+#[cached(time = 2)]
+fn compose_global_template_data() -> TemplateData {
+    return TemplateData {
+        site: get_site_info(),
+        content: dsl::compose_content_from_dsl(),
+        path: None,
+        slug: None,
+        meta: None,
+        entry: None,
+        time_to_read: None,
+        backlinks: None,
+        resources: None,
+        lang: None,
+        translations: None,
+        record: None,
+        prev: None,
+        next: None,
+        related: None,
+    };
+}
+
+/// Return `SiteInfo` from the `site.json` file, on top of the `site.json`
+/// files of the themes.
+#[cached(time = 2)]
+fn get_site_info() -> serde_json::Value {
+    let mut site_info = serde_json::json!({});
+
+    // Themes provide defaults, which the site overrides
+    for dir in site_dirs().iter().rev() {
+        let file_contents = fs::read_to_string(format!("{}{}", dir, "/site.json"));
+        let contents = file_contents.unwrap_or(String::new());
+
+        merge_json(
+            &mut site_info,
+            serde_json::from_str(&contents).unwrap_or(serde_json::from_str("{}").unwrap()),
+        );
+    }
+
+    return site_info;
+}
+
+/// Deeply merges given `overrides` into given `value`, where objects are
+/// merged key by key and anything else is replaced.
+fn merge_json(value: &mut serde_json::Value, overrides: serde_json::Value) {
+    match (value, overrides) {
+        (serde_json::Value::Object(value), serde_json::Value::Object(overrides)) => {
+            for (key, override_value) in overrides {
+                merge_json(value.entry(key).or_insert(serde_json::Value::Null), override_value);
+            }
+        }
+        (value, overrides) => *value = overrides,
+    }
+}
+
+/// Deletes all `FileType::Asset` files from the output directory.
+fn delete_assets() {
+    let assets = find_site_files(FileType::Asset);
+
+    for asset in assets {
+        let relative_path = relative_path(&asset);
+        let public_dir_path = format!("{}{}", &get_config().output_dir, relative_path);
+        let delete = fs::remove_file(public_dir_path);
+
+        if delete.is_err() {
+            println!("{:?}", delete.err());
+        }
+    }
+}
+
+/// Copies all `FileType::Asset` files into the output directory. When
+/// fingerprinting is enabled, also writes a fingerprinted copy of each asset
+/// and the asset manifest mapping logical paths to fingerprinted ones.
+fn copy_assets() {
+    let assets = find_site_files(FileType::Asset);
+
+    for asset in assets {
+        let relative_path = relative_path(&asset);
+        println!("Copying {}", relative_path);
+
+        let full_new_path_str = format!("{}{}", &get_config().output_dir, relative_path);
+        let path = Path::new(&full_new_path_str);
+        let prefix = path.parent().unwrap();
+        let create_dir = fs::create_dir_all(prefix);
+
+        if create_dir.is_err() {
+            println!("{:?}", create_dir.err());
+        }
+
+        let contents = assets::asset_contents(&asset);
+        let action = fs::write(&full_new_path_str, &contents);
+
+        if action.is_err() {
+            report_error(format!("Could not copy file {}", relative_path));
+        }
+
+        if get_config().fingerprint_assets {
+            let entry = assets::asset_manifest_entry(&relative_path, &contents);
+            let action = fs::copy(
+                &full_new_path_str,
+                format!("{}{}", get_config().output_dir, entry.path),
+            );
+
+            if action.is_err() {
+                report_error(format!("Could not copy file {}", entry.path));
+            }
+        }
+    }
+
+    // Copy the resources of translated page bundles next to their pages
+    for (source, relative_path) in localized_bundle_resources() {
+        println!("Copying {}", relative_path);
+
+        let target = format!("{}{}", get_config().output_dir, relative_path);
+        let _ = fs::create_dir_all(Path::new(&target).parent().unwrap());
+
+        if fs::copy(&source, &target).is_err() {
+            report_error(format!("Could not copy file {}", relative_path));
+        }
+    }
+
+    // Compile Sass/SCSS entry points into CSS
+    assets::compile_sass_assets(None);
+
+    // Generate responsive image variants
+    images::process_images();
+
+    if get_config().fingerprint_assets {
+        write_to_path(
+            &format!("{}{}", get_config().output_dir, "/asset-manifest.json"),
+            serde_json::to_string_pretty(&assets::asset_manifest()).unwrap(),
+        );
+    }
+}
+
+/// Runs Oinky on the current directory and compiles an entire static site
+/// out of given information. Returns the paths of the pages written.
+fn compile() -> Vec<String> {
+    println!("Thinking ...");
+
+    // Relative times are relative to the start of the build
+    utils::reset_build_time();
+
+    // Everything is read anew, as files may have changed since the last build
+    clear_caches();
+    invalidate_registry();
+
+    // Empty the public dir
+    empty_public_dir();
+
+    // Construct global Handlebars data
+    let global_data = compose_global_template_data();
+
+    // Report references between content files that could not be resolved
+    links::report_unresolved_links();
+
+    // Compile individual content items
+    let mut pages = compile_content_items(global_data.clone());
+
+    // Compile pages for records of datasets that declare them
+    pages.extend(compile_record_pages(global_data.clone()));
+
+    // Compile individual non-layout and non-partial Handlebars templates.
+    pages.extend(compile_template_items(global_data.clone()));
+
+    // Move assets to the output dir
+    copy_assets();
+
+    // Report how much minifying saved
+    minify::report_savings();
+
+    return pages;
+}
+
+/// Potentially runs Oinky when a given `path` is determined to be something
+/// that would require the site generator to run again. Used by the watcher.
+#[throttle(1, Duration::from_secs(1))]
+fn potentially_compile(path: PathBuf) {
+    let path_str = path.as_path().display().to_string();
+
+    // If the ignore file changed, re-compile everything
+    if path_str == format!("{}{}", get_config().dir, "/.oinkyignore") {
+        compile();
+        print_build_errors();
+        return;
+    }
+
+    // Ignored files and the output are neither built nor copied
+    if is_ignored(&path_str, path.is_dir()) || path_str.starts_with(&get_config().output_dir) {
+        return;
+    }
+
+    // If layouts, partials, pages or helpers changed, the shared registry is
+    // outdated
+    if is_handlebars_file(&path_str) || is_helper_file(&path_str) {
+        invalidate_registry();
+    }
+
+    // If data file, partials/layouts or helpers changed, re-compile everything
+    if is_data_file(&path_str) || is_handlebars_file(&path_str) || is_helper_file(&path_str) {
+        compile();
+    }
+
+    // If assets changed while fingerprinting, the pages referring to them
+    // need to be re-compiled as well
+    if is_asset_file(&path_str) && get_config().fingerprint_assets {
+        compile();
+    }
+    // If assets changed, we need to delete all assets, and copy anew
+    else if is_asset_file(&path_str) {
+        delete_assets();
+        copy_assets();
+    }
+
+    // If Sass files changed, re-compile the entry points that import them
+    if is_sass_file(&path_str) && get_config().fingerprint_assets {
+        compile();
+    } else if is_sass_file(&path_str) {
+        assets::compile_sass_assets(Some(&path_str));
+    }
+
+    // If content items changed, re-compile only those
+    if is_markdown_file(&path_str) {
+        let global_data = compose_global_template_data();
+
+        compile_content_items(global_data.clone());
+        compile_record_pages(global_data.clone());
+        compile_template_items(global_data);
+    }
+
+    // If template items changed, re-compile only those
+    if is_handlebars_page_file(&path_str) {
+        let global_data = compose_global_template_data();
+
+        compile_template_items(global_data);
+    }
+
+    print_build_errors();
+}
+
+/// Prints the errors reported while re-compiling in watch mode, which
+/// don't stop the watcher.
+fn print_build_errors() {
+    for err in take_build_errors() {
+        println!("{}", err);
+    }
+}
+
+/// Watches for file changes and potentially runs Oinky if an interesting enough
+/// file has been created, changed, renamed or deleted. Changes are handled
+/// by given `handle`, which runs them for the right site. Watches given
+/// `dirs`, which are the root directory and the theme directories outside
+/// of it.
+fn watch(dirs: &[String], handle: impl Fn(PathBuf) + Send + Sync + 'static) {
+    let mut h = Hotwatch::new().expect("Watcher failed to initialize.");
+    let handle = Arc::new(handle);
+
+    for dir in dirs {
+        let handle = handle.clone();
+
+        h.watch(dir, move |event: Event| match event {
+            Event::Write(path) => handle(path),
+            Event::Create(path) => handle(path),
+            Event::Rename(_, path) => handle(path),
+            Event::Remove(path) => handle(path),
+            _ => (),
+        })
+            .expect("Failed to watch directory.");
+    }
+
+    loop {
+        thread::park();
+    }
+}
+
+#[test]
+fn test_partial_name() {
+    assert_eq!("card", partial_name("/card.hbs"));
+    assert_eq!("blog/card", partial_name("/blog/card.hbs"));
+    assert_eq!("docs/card", partial_name("/docs/card.handlebars"));
+}
+
+#[test]
+fn test_content_slug() {
+    let _config = lock_config(test_config("/site"));
+    let dir = get_config().dir.clone();
+
+    assert_eq!("/blog/post", content_slug(&format!("{}/blog/post.md", dir)));
+    assert_eq!("/blog/trip", content_slug(&format!("{}/blog/trip/index.md", dir)));
+    assert_eq!("/index", content_slug(&format!("{}/index.md", dir)));
+}
+
+#[test]
+fn test_find_files_skips_themes() {
+    let site = TestSite::new("themes");
+    site.write("index.md", "");
+    site.write("themes/mini/_layouts/post.hbs", "");
+    site.write("themes/mini/about.md", "");
+    site.write("themes/other/index.md", "");
+    site.configure(|config| config.themes = vec![resolve_theme_dir(&site.dir, "mini")]);
+
+    // Directories within themes/ that aren't configured themes are content
+    assert_eq!(
+        vec![site.path("index.md"), site.path("themes/other/index.md")],
+        find_files(site.dir.clone(), FileType::Markdown)
+    );
+    assert_eq!(
+        vec![
+            site.path("index.md"),
+            site.path("themes/other/index.md"),
+            site.path("themes/mini/about.md"),
+        ],
+        find_site_files(FileType::Markdown)
+    );
+    assert_eq!(vec![site.path("themes/mini/_layouts/post.hbs")], find_site_files(FileType::Handlebars));
+}
+
+#[test]
+fn test_script_helpers() {
+    let site = TestSite::new("helpers");
+    site.write(
+        "_helpers/text/pluralize.rhai",
+        "let count = params[0];\nif count == 1 { `one ${params[1]}` } else { `${count} ${hash[\"plural\"]}` }",
+    );
+    site.write("themes/mini/_helpers/pluralize.rhai", "\"theme\"");
+    site.write("page.html.hbs", "{{pluralize 1 \"cat\"}}, {{pluralize 3 \"cat\" plural=\"cats\"}}");
+    site.configure(|config| config.themes = vec![resolve_theme_dir(&site.dir, "mini")]);
+
+    let page = site.path("page.html.hbs");
+    let hbs = build_registry(vec![page.clone()], Vec::new()).unwrap();
+    assert_eq!("one cat, 3 cats", hbs.render(&page, &serde_json::json!({})).unwrap());
+
+    // Helpers in different directories of the site can't share a name
+    site.write("_helpers/numbers/pluralize.rhai", "\"other\"");
+    site.configure(|_| ());
+    clear_caches();
+
+    let err = find_helpers().err().unwrap();
+    assert!(err.contains("are both named pluralize"), "{}", err);
+}
+
+#[test]
+fn test_read_template_source() {
+    let site = TestSite::new("extends");
+    site.write(
+        "_layouts/base.hbs",
+        "---\ntitle: Base\n---\n<title>{{#> title}}Base{{/title}}</title>\
+         <main>{{> @partial-block}}</main><footer>{{#> footer}}Footer{{/footer}}</footer>",
+    );
+    site.write(
+        "_layouts/post.hbs",
+        "---\nextends: base\n---\n{{#*inline \"title\"}}Post{{/inline}}\
+         {{#*inline \"footer\"}}Post footer{{/inline}}<article>{{> @partial-block}}</article>",
+    );
+    site.write(
+        "page.html.hbs",
+        "---\nextends: post\n---\n{{#*inline \"title\"}}Page{{/inline}}{{slug}}",
+    );
+    site.write("plain.html.hbs", "---\ntitle: Plain\n---\n<p>{{slug}}</p>");
+
+    let page = site.path("page.html.hbs");
+    let plain = site.path("plain.html.hbs");
+    let hbs = build_registry(vec![page.clone(), plain.clone()], Vec::new()).unwrap();
+    let data = serde_json::json!({"slug": "/page"});
+
+    assert_eq!(
+        "<title>Page</title><main><article>/page</article></main><footer>Post footer</footer>",
+        hbs.render(&page, &data).unwrap()
+    );
+    assert_eq!("<p>/page</p>", hbs.render(&plain, &data).unwrap());
+}
+
+#[test]
+fn test_is_ignored() {
+    let site = TestSite::new("ignore");
+    site.write(".oinkyignore", "drafts/\n");
+
+    for file in ["index.md", "drafts/new.md", "drafts/keep/a.md", "drafts/old/b.md", "private/c.md"] {
+        site.write(file, "");
+    }
+
+    site.configure(|config| {
+        config.exclude = vec![String::from("/private")];
+        config.include = vec![String::from("/drafts/keep/**")];
+    });
+
+    assert!(!is_ignored(&site.path("drafts"), true));
+    assert!(!is_ignored(&site.path("drafts/keep"), true));
+    assert!(is_ignored(&site.path("drafts/old"), true));
+    assert!(is_ignored(&site.path("private"), true));
+    assert!(is_ignored(&site.path("drafts/new.md"), false));
+    assert!(!is_ignored(&site.path("drafts/keep/a.md"), false));
+    assert!(!is_ignored("/elsewhere/drafts/new.md", false));
+    assert_eq!(
+        vec![site.path("drafts/keep/a.md"), site.path("index.md")],
+        find_files(site.dir.clone(), FileType::Markdown)
+    );
+
+    // Patterns without a slash could match within any directory
+    site.configure(|config| {
+        config.exclude = vec![String::from("/private")];
+        config.include = vec![String::from("b.md")];
+    });
+
+    assert!(!is_ignored(&site.path("drafts/old"), true));
+    assert!(!is_ignored(&site.path("private"), true));
+    assert!(!is_ignored(&site.path("drafts/old/b.md"), false));
+    assert!(is_ignored(&site.path("private/c.md"), false));
+}
+
+#[test]
+fn test_page_bundles() {
+    let site = TestSite::new("bundles");
+
+    for file in [
+        "index.md",
+        "blog/index.md",
+        "blog/post.md",
+        "blog/trip/index.md",
+        "blog/trip/photo.jpg",
+        "blog/trip/maps/route.png",
+        "guides/index.md",
+        "guides/setup/index.md",
+        "guides/setup/shot.png",
+    ] {
+        site.write(file, "");
+    }
+
+    assert!(!is_bundle_index_file(&site.path("index.md")));
+    assert!(!is_bundle_index_file(&site.path("blog/index.md")));
+    assert!(!is_bundle_index_file(&site.path("guides/index.md")));
+    assert!(is_bundle_index_file(&site.path("blog/trip/index.md")));
+    assert!(is_bundle_index_file(&site.path("guides/setup/index.md")));
+    assert_eq!("/blog/index", content_slug(&site.path("blog/index.md")));
+    assert_eq!("/blog/trip", content_slug(&site.path("blog/trip/index.md")));
+
+    assert_eq!(
+        vec![ContentResource {
+            name: String::from("photo.jpg"),
+            path: String::from("/blog/trip/photo.jpg"),
+        }],
+        find_bundle_resources(&site.path("blog/trip/index.md"))
+    );
+    assert_eq!(1, find_bundle_resources(&site.path("guides/setup/index.md")).len());
+    assert!(find_bundle_resources(&site.path("guides/index.md")).is_empty());
+}
+
+#[test]
+fn test_localized_bundle_resources() {
+    let site = TestSite::new("i18n-bundles");
+
+    for file in ["blog/trip/index.md", "blog/trip/index.et.md", "blog/trip/photo.jpg", "blog/hike/index.md"] {
+        site.write(file, "");
+    }
+
+    site.configure(|config| config.languages = vec![String::from("en"), String::from("et")]);
+
+    assert_eq!("/et/blog/trip", content_slug(&site.path("blog/trip/index.et.md")));
+    assert_eq!(
+        vec![(site.path("blog/trip/photo.jpg"), String::from("/et/blog/trip/photo.jpg"))],
+        localized_bundle_resources()
+    );
+}
+
+/// Prints how long rendering takes with a registry per page and with a
+/// shared registry, run with `cargo test -- --ignored`. Only a measurement,
+/// as timings vary too much between machines to assert on.
+#[test]
+#[ignore]
+fn bench_shared_registry() {
+    use std::time::Instant;
+
+    let site = TestSite::new("bench");
+    let mut partials: Vec<TemplatePartial> = Vec::new();
+
+    for i in 0..20 {
+        let path = format!("_partials/partial{}.hbs", i);
+        site.write(&path, format!("<div>{{{{site.title}}}} {}</div>", i));
+        partials.push(TemplatePartial {
+            name: format!("partial{}", i),
+            path: site.path(&path),
+        });
+    }
+
+    let template_path = site.path("page.hbs");
+    let template: String = (0..20).map(|i| format!("{{{{> partial{}}}}}", i)).collect();
+    site.write("page.hbs", format!("<html><body>{}{{{{slug}}}}</body></html>", template));
+
+    let data = TemplateData {
+        site: serde_json::json!({"title": "Bench"}),
+        ..compose_global_template_data()
+    };
+    let pages = 2000;
+
+    // A registry per page, as it used to be
+    let start = Instant::now();
+
+    for _ in 0..pages {
+        let hbs = build_registry(vec![template_path.clone()], partials.clone()).unwrap();
+        hbs.render(&template_path, &data).unwrap();
+    }
+
+    let per_page = start.elapsed();
+
+    // A registry shared by all pages
+    let start = Instant::now();
+    let hbs = build_registry(vec![template_path.clone()], partials.clone()).unwrap();
+
+    for _ in 0..pages {
+        hbs.render(&template_path, &data).unwrap();
+    }
+
+    let shared = start.elapsed();
+
+    println!("{} pages: registry per page {:?}, shared registry {:?}", pages, per_page, shared);
+}
+
+#[test]
+fn test_build_record_slug() {
+    let record = serde_json::json!({"id": 7, "name": "Tom & Jerry's", "path": "../../etc"});
+
+    assert_eq!(Ok(String::from("/products/7")), build_record_slug("/products/{{id}}/", &record));
+    assert_eq!(Ok(String::from("/products/7")), build_record_slug("products//{{id}}", &record));
+    assert_eq!(
+        Ok(String::from("/shows/Tom & Jerry's")),
+        build_record_slug("/shows/{{name}}", &record)
+    );
+    assert!(build_record_slug("/files/{{path}}", &record).is_err());
+    assert!(build_record_slug("{{missing}}", &record).is_err());
+    assert!(build_record_slug("/products/{{#if}}", &record).is_err());
+}
+
+#[test]
+fn test_find_record_pages() {
+    let site = TestSite::new("records");
+    site.write(
+        "products.json",
+        r#"[{"id": 1}, {"id": 2}, {"id": 1}, {"id": ".."}, {"id": "sale"}, {"id": "gift"}]"#,
+    );
+    site.write("products/sale.md", "");
+    site.write("products/gift/index.html.hbs", "");
+    site.write(
+        "content.json",
+        r#"[{"name": "products", "from": "products.json", "pages": {"layout": "product", "slug": "/products/{{id}}"}}]"#,
+    );
+    take_build_errors();
+
+    let slugs: Vec<String> = find_record_pages(&compose_global_template_data())
+        .into_iter()
+        .map(|page| page.slug)
+        .collect();
+    assert_eq!(vec!["/products/1", "/products/2"], slugs);
+
+    let errors = take_build_errors();
+    assert_eq!(4, errors.len());
+    assert!(errors[2].ends_with("/products/sale as /products/sale.md"), "{}", errors[2]);
+    assert!(errors[3].ends_with("/products/gift as /products/gift/index.html.hbs"), "{}", errors[3]);
+}
+
+#[test]
+fn test_merge_json() {
+    let mut value = serde_json::json!({"title": "Theme", "author": {"name": "A", "url": "/a"}, "tags": [1]});
+    merge_json(
+        &mut value,
+        serde_json::json!({"title": "Site", "author": {"name": "B"}, "tags": [2, 3]}),
+    );
+
+    assert_eq!(
+        serde_json::json!({"title": "Site", "author": {"name": "B", "url": "/a"}, "tags": [2, 3]}),
+        value
+    );
+}
+
+#[test]
+fn test_parse_content_file_meta() {
+    let meta = parse_content_file_meta(String::from("---\ntitle: Hello\n\ntime: 10:30\n...\nBody")).unwrap();
+    assert_eq!("Hello", meta["title"]);
+    assert_eq!("10:30", meta["time"]);
+
+    let err = parse_content_file_meta(String::from("---\ntitle Hello\n---\n")).err().unwrap();
+    assert!(err.contains("title Hello"), "{}", err);
+}
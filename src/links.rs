@@ -0,0 +1,303 @@
+use super::{content_slug, find_files, get_config, parse_content_file_meta, report_error, FileType};
+use cached::proc_macro::cached;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentBacklink {
+    pub path: String,
+    pub slug: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ContentLinkIndex {
+    pub slugs: HashMap<String, String>,
+    pub names: HashMap<String, String>,
+    pub backlinks: HashMap<String, Vec<ContentBacklink>>,
+    pub unresolved: Vec<(String, String)>,
+}
+
+/// Matches Markdown links to content files, see `markdown_link_regex`.
+static MARKDOWN_LINK_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// Matches wiki-style links, see `wiki_link_regex`.
+static WIKI_LINK_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// Matches Markdown links to content files, such as `[x](../guides/setup.md)`.
+fn markdown_link_regex() -> &'static Regex {
+    return MARKDOWN_LINK_REGEX.get_or_init(|| {
+        Regex::new(r"(!?)\[([^\]]*)\]\(([^()\s]+?\.(?:md|markdown))(#[^()\s]*)?\)").unwrap()
+    });
+}
+
+/// Matches wiki-style links, such as `[[Page Title]]`, `[[Page Title|text]]`
+/// and `[[Page Title#heading]]`.
+fn wiki_link_regex() -> &'static Regex {
+    return WIKI_LINK_REGEX
+        .get_or_init(|| Regex::new(r"\[\[([^\[\]|#]+)(#[^\[\]|]*)?(?:\|([^\[\]]+))?\]\]").unwrap());
+}
+
+/// Applies given `transform` to the parts of given Markdown `contents` that
+/// are not code, meaning fenced code blocks and inline code spans are left
+/// untouched.
+fn map_non_code(contents: &str, mut transform: impl FnMut(&str) -> String) -> String {
+    let mut result: Vec<String> = Vec::new();
+    let mut in_fence = false;
+
+    for line in contents.split('\n') {
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            result.push(line.to_string());
+            continue;
+        }
+
+        if in_fence {
+            result.push(line.to_string());
+            continue;
+        }
+
+        let parts: Vec<String> = line
+            .split('`')
+            .enumerate()
+            .map(|(index, part)| {
+                if index % 2 == 0 {
+                    transform(part)
+                } else {
+                    part.to_string()
+                }
+            })
+            .collect();
+
+        result.push(parts.join("`"));
+    }
+
+    return result.join("\n");
+}
+
+/// Normalizes a given `path` by resolving `.` and `..` components without
+/// touching the file system.
+pub fn normalize_path(path: &Path) -> String {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => (),
+            component => normalized.push(component),
+        }
+    }
+
+    return normalized.display().to_string();
+}
+
+/// Resolves a Markdown link `target` found in a content file in a given
+/// `path` into the absolute path of the content file it points to. Targets
+/// starting with `/` are relative to the root directory, others to the
+/// directory of the content file.
+fn resolve_link_target(path: &str, target: &str) -> String {
+    if target.starts_with('/') {
+        return normalize_path(Path::new(&format!("{}{}", get_config().dir, target)));
+    }
+
+    let dir = Path::new(path).parent().unwrap_or(Path::new("/"));
+
+    return normalize_path(&dir.join(target));
+}
+
+/// Returns the path of the content file a wiki link `name` points to, by
+/// matching it against the titles, file names and slugs of content files.
+fn resolve_wiki_name(index: &ContentLinkIndex, name: &str) -> Option<String> {
+    let key = name.trim().trim_start_matches('/').to_lowercase();
+
+    return index.names.get(&key).cloned();
+}
+
+/// Builds the link index of all content files within the root directory,
+/// which holds the slugs of the files, the names wiki links can refer to
+/// them by, the backlinks of each file and any references that could not be
+/// resolved.
+#[cached(time = 2)]
+pub fn content_link_index() -> Arc<ContentLinkIndex> {
+    let files = find_files(get_config().dir.clone(), FileType::Markdown);
+    let mut index = ContentLinkIndex::default();
+    let mut titles: HashMap<String, String> = HashMap::new();
+    let mut contents: HashMap<String, String> = HashMap::new();
+
+    for file in &files {
+        let file_contents = fs::read_to_string(file).unwrap_or_default();
+        let meta = parse_content_file_meta(file_contents.clone()).unwrap_or_default();
+        let slug = content_slug(file);
+        let stem = Path::new(&slug)
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let title = meta.get("title").cloned().unwrap_or(stem.clone());
+
+        index.slugs.insert(file.clone(), slug.clone());
+        index.names.entry(stem.to_lowercase()).or_insert(file.clone());
+        index
+            .names
+            .insert(slug.trim_start_matches('/').to_lowercase(), file.clone());
+        titles.insert(file.clone(), title);
+        contents.insert(file.clone(), file_contents);
+    }
+
+    // Titles win over file names and slugs.
+    for (file, title) in &titles {
+        index.names.insert(title.to_lowercase(), file.clone());
+    }
+
+    for file in &files {
+        let mut targets: Vec<String> = Vec::new();
+
+        map_non_code(&contents[file], |part| {
+            for captures in markdown_link_regex().captures_iter(part) {
+                if &captures[1] == "!" || captures[3].contains("://") {
+                    continue;
+                }
+
+                let target = resolve_link_target(file, &captures[3]);
+
+                if index.slugs.contains_key(&target) {
+                    targets.push(target);
+                } else {
+                    index.unresolved.push((file.clone(), captures[3].to_string()));
+                }
+            }
+
+            for captures in wiki_link_regex().captures_iter(part) {
+                match resolve_wiki_name(&index, &captures[1]) {
+                    Some(target) => targets.push(target),
+                    None => index.unresolved.push((file.clone(), captures[0].to_string())),
+                }
+            }
+
+            return part.to_string();
+        });
+
+        targets.sort();
+        targets.dedup();
+
+        for target in targets.into_iter().filter(|target| target != file) {
+            index.backlinks.entry(target).or_default().push(ContentBacklink {
+                path: file.clone(),
+                slug: index.slugs[file].clone(),
+                title: titles[file].clone(),
+            });
+        }
+    }
+
+    for backlinks in index.backlinks.values_mut() {
+        backlinks.sort_by(|a, b| a.slug.cmp(&b.slug));
+    }
+
+    index.unresolved.sort();
+
+    return Arc::new(index);
+}
+
+/// Resolves links to other content files within given Markdown `contents`
+/// of a content file in a given `path` into the URLs of those content items.
+/// Supports Markdown links to source files, such as
+/// `[x](../guides/setup.md#install)`, and wiki-style links, such as
+/// `[[Page Title]]` or `[[Page Title|link text]]`. References that can't be
+/// resolved are left as they are.
+pub fn resolve_links(path: &str, contents: &str) -> String {
+    let index = content_link_index();
+
+    return map_non_code(contents, |part| {
+        let part = markdown_link_regex().replace_all(part, |captures: &Captures| {
+            if &captures[1] == "!" || captures[3].contains("://") {
+                return captures[0].to_string();
+            }
+
+            let target = resolve_link_target(path, &captures[3]);
+
+            return match index.slugs.get(&target) {
+                Some(slug) => format!(
+                    "[{}]({}{})",
+                    &captures[2],
+                    slug,
+                    captures.get(4).map(|m| m.as_str()).unwrap_or_default()
+                ),
+                None => captures[0].to_string(),
+            };
+        });
+
+        let part = wiki_link_regex().replace_all(&part, |captures: &Captures| {
+            let target = resolve_wiki_name(&index, &captures[1]);
+
+            return match target.and_then(|target| index.slugs.get(&target)) {
+                Some(slug) => format!(
+                    "[{}]({}{})",
+                    captures.get(3).map(|m| m.as_str()).unwrap_or(captures[1].trim()),
+                    slug,
+                    captures.get(2).map(|m| m.as_str()).unwrap_or_default()
+                ),
+                None => captures[0].to_string(),
+            };
+        });
+
+        return part.to_string();
+    });
+}
+
+/// Prints a warning listing all references between content files that could
+/// not be resolved. When `STRICT_LINKS` is enabled, fails the build instead.
+pub fn report_unresolved_links() {
+    let index = content_link_index();
+
+    if index.unresolved.is_empty() {
+        return;
+    }
+
+    let mut report = String::from("Unresolved references:\n");
+
+    for (file, reference) in &index.unresolved {
+        let relative_path = file.replace(&get_config().dir, "");
+        report.push_str(&format!("  {} in {}\n", reference, relative_path));
+    }
+
+    if get_config().strict_links {
+        report_error(report);
+    } else {
+        print!("{}", report);
+    }
+}
+
+#[test]
+fn test_map_non_code() {
+    let contents = "[[A]] `[[B]]`\n```\n[[C]]\n```\n[[D]]";
+    let result = map_non_code(contents, |part| part.replace("[[", "<<"));
+
+    assert_eq!("<<A]] `[[B]]`\n```\n[[C]]\n```\n<<D]]", result);
+}
+
+#[test]
+fn test_resolve_link_target() {
+    let _config = super::lock_config(super::test_config("/site"));
+    let dir = get_config().dir.clone();
+    let path = format!("{}/blog/post.md", dir);
+
+    assert_eq!(
+        format!("{}/guides/setup.md", dir),
+        resolve_link_target(&path, "../guides/setup.md")
+    );
+    assert_eq!(
+        format!("{}/blog/other.md", dir),
+        resolve_link_target(&path, "./other.md")
+    );
+    assert_eq!(
+        format!("{}/about.md", dir),
+        resolve_link_target(&path, "/about.md")
+    );
+}
@@ -0,0 +1,160 @@
+use super::get_config;
+use cached::proc_macro::cached;
+use lightningcss::stylesheet::{MinifyOptions, ParserOptions, PrinterOptions, StyleSheet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+static FILES: AtomicUsize = AtomicUsize::new(0);
+static BYTES_BEFORE: AtomicUsize = AtomicUsize::new(0);
+static BYTES_AFTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Determines if the file written to a given `path` within the output
+/// directory should be minified, which is the case in production mode unless
+/// the path matches one of the `MINIFY_EXCLUDE` glob patterns, such as
+/// `/js/vendor/*`.
+pub fn should_minify(path: &str) -> bool {
+    if !get_config().production {
+        return false;
+    }
+
+    return !minify_exclude_patterns().iter().any(|pattern| pattern.matches(path));
+}
+
+/// Compiles the `MINIFY_EXCLUDE` glob patterns, leaving out invalid ones.
+#[cached(time = 2)]
+pub(crate) fn minify_exclude_patterns() -> Arc<Vec<glob::Pattern>> {
+    let patterns = get_config()
+        .minify_exclude
+        .iter()
+        .filter_map(|pattern| match glob::Pattern::new(pattern) {
+            Ok(pattern) => Some(pattern),
+            Err(err) => {
+                println!("Could not parse MINIFY_EXCLUDE pattern {}: {}", pattern, err);
+                None
+            }
+        })
+        .collect();
+
+    return Arc::new(patterns);
+}
+
+/// Records the size of a file `before` and `after` minification for the
+/// report printed at the end of the build.
+fn record_savings(before: usize, after: usize) {
+    FILES.fetch_add(1, Ordering::SeqCst);
+    BYTES_BEFORE.fetch_add(before, Ordering::SeqCst);
+    BYTES_AFTER.fetch_add(after, Ordering::SeqCst);
+}
+
+/// Minifies given `html`, including inline CSS. Inline JS is left as it is.
+pub fn minify_html(html: String) -> String {
+    let mut cfg = minify_html::Cfg::new();
+    cfg.minify_css = true;
+    cfg.keep_html_and_head_opening_tags = true;
+    cfg.do_not_minify_doctype = true;
+
+    let minified = String::from_utf8(minify_html::minify(html.as_bytes(), &cfg)).unwrap_or(html.clone());
+    record_savings(html.len(), minified.len());
+
+    return minified;
+}
+
+/// Minifies given `css`, or returns an error when it can't be parsed.
+pub fn minify_css(css: &str) -> Result<String, String> {
+    let mut stylesheet = StyleSheet::parse(css, ParserOptions::default()).map_err(|e| e.to_string())?;
+    stylesheet
+        .minify(MinifyOptions::default())
+        .map_err(|e| e.to_string())?;
+    let minified = stylesheet
+        .to_css(PrinterOptions {
+            minify: true,
+            ..PrinterOptions::default()
+        })
+        .map_err(|e| e.to_string())?
+        .code;
+
+    record_savings(css.len(), minified.len());
+
+    return Ok(minified);
+}
+
+/// Minifies given `js` by removing comments and whitespace, without
+/// rewriting any of the code itself.
+pub fn minify_js(js: &str) -> Result<String, String> {
+    let minified = minifier::js::minify(js).to_string();
+    record_savings(js.len(), minified.len());
+
+    return Ok(minified);
+}
+
+/// Minifies given `contents` of an asset written to a given `path` within
+/// the output directory if it is a CSS or JS file that should be minified
+/// and isn't minified already. Assets that fail to minify are returned as
+/// they are.
+pub fn minify_asset(path: &str, contents: Vec<u8>) -> Vec<u8> {
+    let is_css = path.ends_with(".css");
+    let is_js = path.ends_with(".js");
+
+    if !(is_css || is_js) || path.contains(".min.") || !should_minify(path) {
+        return contents;
+    }
+
+    let source = match String::from_utf8(contents.clone()) {
+        Ok(source) => source,
+        Err(_) => return contents,
+    };
+
+    let minified = if is_css {
+        minify_css(&source)
+    } else {
+        minify_js(&source)
+    };
+
+    match minified {
+        Ok(minified) => return minified.into_bytes(),
+        Err(err) => {
+            println!("Could not minify {}: {}", path, err);
+            return contents;
+        }
+    }
+}
+
+/// Prints the number of minified files and the bytes saved by minifying
+/// them, and resets the counters for the next build.
+pub fn report_savings() {
+    let files = FILES.swap(0, Ordering::SeqCst);
+    let before = BYTES_BEFORE.swap(0, Ordering::SeqCst);
+    let after = BYTES_AFTER.swap(0, Ordering::SeqCst);
+
+    if files == 0 {
+        return;
+    }
+
+    let saved = before.saturating_sub(after);
+    let percentage = if before > 0 {
+        saved as f64 / before as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    println!(
+        "Minified {} files, saved {:.1} KB ({:.1}%)",
+        files,
+        saved as f64 / 1024.0,
+        percentage
+    );
+}
+
+#[test]
+fn test_minify() {
+    let html = minify_html(String::from(
+        "<!DOCTYPE html>\n<html>\n  <head>\n    <style> a { color : red ; } </style>\n  </head>\n  <body>\n    <p>  Hi  </p>\n  </body>\n</html>\n",
+    ));
+    assert_eq!("<!doctype html><html><head><style>a{color:red}</style><body><p>Hi", html);
+
+    assert_eq!("a b{color:red}", minify_css("a b {\n  color: #ff0000;\n}\n").unwrap());
+    assert_eq!(
+        "function hello(name){console.log(\"hi \"+name);}",
+        minify_js("// Greets\nfunction hello(name) {\n  console.log(\"hi \" + name);\n}\n").unwrap()
+    );
+}
@@ -0,0 +1,264 @@
+use super::{
+    check, compile, dsl, find_files, get_config, load_config, parse_content_files, potentially_compile,
+    render_page, resolve_dir, resolve_theme_dir, set_config, take_build_errors, validate_config, watch,
+    BUILD_LOCK, CheckIssue, Config, ContentItem, FileType, TemplateContentDSLItem,
+};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Builds a `Site`. Options that are not set are read from the `oinky.toml`
+/// file of the site and the environment, the same as when running the
+/// `oinky` binary.
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    source_dir: Option<String>,
+    output_dir: Option<String>,
+    production: Option<bool>,
+    fingerprint_assets: Option<bool>,
+    strict_links: Option<bool>,
+    themes: Option<Vec<String>>,
+    languages: Option<Vec<String>>,
+    timezone: Option<String>,
+    locale: Option<String>,
+    jobs: Option<usize>,
+}
+
+/// A site that is built from a source directory into an output directory.
+/// Oinky builds one site at a time, so the methods of different sites wait
+/// for each other.
+#[derive(Clone)]
+pub struct Site {
+    config: Config,
+    pool: Option<Arc<ThreadPool>>,
+}
+
+/// The result of building a site.
+#[derive(Debug, Clone, Default)]
+pub struct BuildResult {
+    /// Paths of the pages written, relative to the output directory.
+    pub pages: Vec<String>,
+    /// Errors that failed the build.
+    pub errors: Vec<String>,
+    /// Time the build took.
+    pub duration: Duration,
+}
+
+impl BuildResult {
+    /// Determines if the build succeeded.
+    pub fn is_ok(&self) -> bool {
+        return self.errors.is_empty();
+    }
+}
+
+impl Builder {
+    /// Starts building a site with the default options.
+    pub fn new() -> Builder {
+        return Builder::default();
+    }
+
+    /// Sets the directory the site is built from, `READ_DIR` or the current
+    /// directory by default.
+    pub fn source_dir(mut self, dir: impl Into<String>) -> Builder {
+        self.source_dir = Some(dir.into());
+        return self;
+    }
+
+    /// Sets the directory the site is built into, relative to the source
+    /// directory unless absolute. `OUTPUT_DIR` or `public` by default.
+    pub fn output_dir(mut self, dir: impl Into<String>) -> Builder {
+        self.output_dir = Some(dir.into());
+        return self;
+    }
+
+    /// Enables or disables production mode, which minifies the output.
+    pub fn production(mut self, production: bool) -> Builder {
+        self.production = Some(production);
+        return self;
+    }
+
+    /// Enables or disables fingerprinting of assets.
+    pub fn fingerprint_assets(mut self, fingerprint_assets: bool) -> Builder {
+        self.fingerprint_assets = Some(fingerprint_assets);
+        return self;
+    }
+
+    /// Enables or disables failing the build on unresolved links.
+    pub fn strict_links(mut self, strict_links: bool) -> Builder {
+        self.strict_links = Some(strict_links);
+        return self;
+    }
+
+    /// Sets the themes of the site, by name or path, in order of priority.
+    pub fn themes(mut self, themes: Vec<String>) -> Builder {
+        self.themes = Some(themes);
+        return self;
+    }
+
+    /// Sets the languages of the site, the first being the default.
+    pub fn languages(mut self, languages: Vec<String>) -> Builder {
+        self.languages = Some(languages);
+        return self;
+    }
+
+    /// Sets the timezone of the site, an IANA name or a UTC offset.
+    pub fn timezone(mut self, timezone: impl Into<String>) -> Builder {
+        self.timezone = Some(timezone.into());
+        return self;
+    }
+
+    /// Sets the locale of month and day names, such as `et_EE`.
+    pub fn locale(mut self, locale: impl Into<String>) -> Builder {
+        self.locale = Some(locale.into());
+        return self;
+    }
+
+    /// Sets the number of pages built in parallel, 0 for one per CPU.
+    pub fn jobs(mut self, jobs: usize) -> Builder {
+        self.jobs = Some(jobs);
+        return self;
+    }
+
+    /// Builds the site, or returns an error when its config is invalid.
+    pub fn build(self) -> Result<Site, String> {
+        let current_dir = env::current_dir()
+            .map_err(|err| format!("Could not read the current directory: {}", err))?
+            .display()
+            .to_string();
+        let source_dir = self
+            .source_dir
+            .or(env::var("READ_DIR").ok())
+            .unwrap_or(current_dir.clone());
+        let source_dir = resolve_dir(&current_dir, &source_dir);
+        let mut config = load_config(source_dir.clone())?;
+
+        if let Some(output_dir) = self.output_dir {
+            config.output_dir = resolve_dir(&source_dir, &output_dir);
+        }
+
+        if let Some(themes) = self.themes {
+            config.themes = themes
+                .iter()
+                .filter(|theme| !theme.trim().is_empty())
+                .map(|theme| resolve_theme_dir(&source_dir, theme))
+                .collect();
+        }
+
+        config.production = self.production.unwrap_or(config.production);
+        config.fingerprint_assets = self.fingerprint_assets.unwrap_or(config.fingerprint_assets);
+        config.strict_links = self.strict_links.unwrap_or(config.strict_links);
+        config.languages = self.languages.unwrap_or(config.languages);
+        config.timezone = self.timezone.unwrap_or(config.timezone);
+        config.locale = self.locale.or(config.locale);
+        config.jobs = self.jobs.unwrap_or(config.jobs);
+
+        validate_config(&config)?;
+
+        // Bound the number of pages built in parallel
+        let pool = match config.jobs {
+            0 => None,
+            jobs => Some(Arc::new(
+                ThreadPoolBuilder::new()
+                    .num_threads(jobs)
+                    .build()
+                    .map_err(|err| format!("Failed to set up the thread pool: {}", err))?,
+            )),
+        };
+
+        return Ok(Site { config, pool });
+    }
+}
+
+impl Site {
+    /// Returns the directory the site is built from.
+    pub fn source_dir(&self) -> &str {
+        return &self.config.dir;
+    }
+
+    /// Returns the directory the site is built into.
+    pub fn output_dir(&self) -> &str {
+        return &self.config.output_dir;
+    }
+
+    /// Runs given `f` with the config of this site, on its thread pool. Waits
+    /// for other sites to finish first.
+    fn run<T: Send>(&self, f: impl FnOnce() -> T + Send) -> T {
+        let _lock = BUILD_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+        set_config(&self.config);
+
+        return match &self.pool {
+            Some(pool) => pool.install(f),
+            None => f(),
+        };
+    }
+
+    /// Loads all content items of the site.
+    pub fn load_content(&self) -> Vec<ContentItem> {
+        return self.run(|| parse_content_files(find_files(get_config().dir.clone(), FileType::Markdown)));
+    }
+
+    /// Composes the datasets of the `content.json` DSL of the site, by name.
+    pub fn compose_data(&self) -> HashMap<String, TemplateContentDSLItem> {
+        return self.run(dsl::compose_content_from_dsl);
+    }
+
+    /// Renders the page of a content file or Handlebars page in a given
+    /// `path`, absolute or relative to the source directory, into HTML
+    /// without writing it to disk.
+    pub fn render_page(&self, path: &str) -> Result<String, String> {
+        let path = resolve_dir(&self.config.dir, path);
+
+        return self.run(|| render_page(&path));
+    }
+
+    /// Builds the entire site into the output directory.
+    pub fn build(&self) -> BuildResult {
+        return self.run(|| {
+            let start = Instant::now();
+
+            // Errors left over from an earlier build are not ours
+            take_build_errors();
+
+            let pages = compile();
+
+            return BuildResult {
+                pages,
+                errors: take_build_errors(),
+                duration: start.elapsed(),
+            };
+        });
+    }
+
+    /// Checks the output directory for broken links and missing resources,
+    /// including links to other sites when `external` is enabled, and prints
+    /// a report of the issues found.
+    pub fn check(&self, external: bool) -> Vec<CheckIssue> {
+        return self.run(|| check::check(external));
+    }
+
+    /// Watches the source directory for changes and re-builds what they
+    /// affect. Never returns.
+    pub fn watch(&self) {
+        let site = self.clone();
+
+        // Themes within the root directory are watched along with it
+        let dirs: Vec<String> = std::iter::once(self.config.dir.clone())
+            .chain(
+                self.config
+                    .themes
+                    .iter()
+                    .filter(|theme| !theme.starts_with(&format!("{}/", self.config.dir)))
+                    .cloned(),
+            )
+            .collect();
+
+        // The watcher itself doesn't run on the pool, as it never returns
+        watch(&dirs, move |path: PathBuf| {
+            site.run(|| potentially_compile(path).unwrap_or(()));
+        });
+    }
+}
@@ -1,35 +1,406 @@
-use super::ContentItem;
+use super::{get_config, ContentItem};
+use cached::proc_macro::cached;
+use chrono::format::{Item, StrftimeItems};
+use chrono::prelude::*;
+use chrono_tz::Tz;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_value::Value;
+use std::cmp::Ordering;
+use std::fmt::{Display, Write};
+use std::sync::RwLock;
 
-/// Sorts given `items` by given `by` in given `order`. Supports top-level struct
-/// keys as `by` as well as meta-level keys like `meta.date`.
-pub fn sort_content_items(items: &mut Vec<ContentItem>, by: String, order: String) {
-    items.sort_by(|a, b| {
-        if by.contains("meta.") {
-            let meta_key = by.replace("meta.", "");
-            let comp_a = a.meta.get(&meta_key);
-            let comp_b = b.meta.get(&meta_key);
-
-            return if order == "desc" {
-                comp_b.cmp(&comp_a)
-            } else {
-                comp_a.cmp(&comp_b)
-            };
-        } else {
-            let comp_a: String = get_field_by_name(a, &by);
-            let comp_b: String = get_field_by_name(b, &by);
-
-            return if order == "desc" {
-                comp_b.cmp(&comp_a)
-            } else {
-                comp_a.cmp(&comp_b)
+/// The time the current build started at, see `build_time`.
+static BUILD_TIME: RwLock<Option<DateTime<Utc>>> = RwLock::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timezone {
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl Timezone {
+    /// Parses a given `value` into a timezone, which is either an IANA
+    /// timezone name, such as `Asia/Kolkata`, or a UTC offset in hours or in
+    /// hours and minutes, such as `2`, `-3` or `+05:30`.
+    pub fn parse(value: &str) -> Option<Timezone> {
+        let value = value.trim();
+
+        if let Ok(tz) = value.parse::<Tz>() {
+            return Some(Timezone::Named(tz));
+        }
+
+        let (sign, offset) = match value.strip_prefix('-') {
+            Some(offset) => (-1, offset),
+            None => (1, value.trim_start_matches('+')),
+        };
+        let (hours, minutes) = match offset.split_once(':') {
+            Some((hours, minutes)) => (hours, minutes),
+            None if offset.len() == 4 => offset.split_at(2),
+            None => (offset, "0"),
+        };
+        let seconds = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+
+        return FixedOffset::east_opt(sign * seconds).map(Timezone::Fixed);
+    }
+
+    /// Interprets a given `naive` date-time as a local date-time within this
+    /// timezone. Local times that occur twice resolve to the earlier one.
+    pub fn localize(&self, naive: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        return match self {
+            Timezone::Named(tz) => tz.from_local_datetime(naive).earliest().map(|dt| dt.fixed_offset()),
+            Timezone::Fixed(offset) => offset.from_local_datetime(naive).earliest(),
+        };
+    }
+}
+
+/// Returns the timezone of the site, configured with `TIMEZONE` as an IANA
+/// timezone name or a UTC offset. Defaults to UTC.
+pub fn site_timezone() -> Timezone {
+    return Timezone::parse(&get_config().timezone).unwrap_or(Timezone::Fixed(FixedOffset::east_opt(0).unwrap()));
+}
+
+/// Returns the time the current build started at, which relative times are
+/// relative to.
+pub fn build_time() -> DateTime<Utc> {
+    if let Some(time) = *BUILD_TIME.read().unwrap() {
+        return time;
+    }
+
+    return *BUILD_TIME.write().unwrap().get_or_insert(Utc::now());
+}
+
+/// Starts the clock of a new build, see `build_time`.
+pub fn reset_build_time() {
+    *BUILD_TIME.write().unwrap() = Some(Utc::now());
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub by: String,
+    pub order: String,
+    pub compare: String,
+    pub missing: String,
+}
+
+/// Sorts given `items` by given sort `keys`, each with its own order,
+/// comparison type and placement of missing values. Supports top-level struct
+/// keys as well as meta-level keys like `meta.date`. Items that compare equal
+/// on all keys are ordered by their path, so that the result is the same
+/// between builds.
+pub fn sort_content_items(items: &mut [ContentItem], keys: &[SortKey]) {
+    let keys: Vec<SortKey> = keys
+        .iter()
+        .map(|key| {
+            let values: Vec<String> = items.iter().filter_map(|item| get_sort_value(item, &key.by)).collect();
+
+            return SortKey {
+                compare: column_compare(&values, &key.compare),
+                ..key.clone()
             };
+        })
+        .collect();
+
+    items.sort_by(|a, b| {
+        for key in &keys {
+            let ordering = compare_sort_values(
+                get_sort_value(a, &key.by),
+                get_sort_value(b, &key.by),
+                key,
+            );
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
         }
+
+        return a.path.cmp(&b.path);
     });
 }
 
+/// Returns the value of a given `item` to sort by for a given `by`, which can
+/// be a top-level struct key or a meta-level key like `meta.date`. Empty
+/// values are considered missing.
+fn get_sort_value(item: &ContentItem, by: &str) -> Option<String> {
+    let value = match by.strip_prefix("meta.") {
+        Some(meta_key) => item.meta.get(meta_key).cloned(),
+        None => match get_field_by_name(item, by) {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(value) => Some(value),
+            value => Some(value.to_string()),
+        },
+    };
+
+    return value.filter(|v| !v.trim().is_empty());
+}
+
+/// Compares sort values `a` and `b` according to given sort `key`. Missing
+/// values are placed either `first` or `last` regardless of the order.
+fn compare_sort_values(a: Option<String>, b: Option<String>, key: &SortKey) -> Ordering {
+    let missing_first = key.missing == "first";
+
+    let ordering = match (a, b) {
+        (None, None) => return Ordering::Equal,
+        (None, Some(_)) if missing_first => return Ordering::Less,
+        (None, Some(_)) => return Ordering::Greater,
+        (Some(_), None) if missing_first => return Ordering::Greater,
+        (Some(_), None) => return Ordering::Less,
+        (Some(a), Some(b)) => compare_values(&a, &b, &key.compare),
+    };
+
+    return if key.order == "desc" {
+        ordering.reverse()
+    } else {
+        ordering
+    };
+}
+
+/// Resolves the given `compare` type into the type that a column of
+/// `values` is compared by. The `auto` type compares numerically if all
+/// values are numbers, by date if all are dates and naturally otherwise.
+/// The `numeric` and `date` types fall back to natural comparison unless all
+/// values are of that type. Comparing the whole column by one type keeps the
+/// order consistent, which comparing each pair of values by its own type
+/// would not.
+pub fn column_compare<S: AsRef<str>>(values: &[S], compare: &str) -> String {
+    let all_numeric = || values.iter().all(|v| v.as_ref().trim().parse::<f64>().is_ok());
+    let all_dates = || values.iter().all(|v| parse_date(v.as_ref()).is_some());
+
+    let compare = match compare {
+        "numeric" if all_numeric() => "numeric",
+        "date" if all_dates() => "date",
+        "auto" if all_numeric() => "numeric",
+        "auto" if all_dates() => "date",
+        "numeric" | "date" | "auto" => "natural",
+        compare => compare,
+    };
+
+    return compare.to_string();
+}
+
+/// Compares values `a` and `b` by the given `compare` type, which is one of
+/// `numeric`, `date`, `natural`, `string`, `case_insensitive` or `auto`. To
+/// sort a column of values, resolve its type with `column_compare` first, as
+/// `a` and `b` alone are compared by the type that fits both.
+pub fn compare_values(a: &str, b: &str, compare: &str) -> Ordering {
+    return match column_compare(&[a, b], compare).as_str() {
+        "numeric" => match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.total_cmp(&b),
+            _ => compare_natural(a, b),
+        },
+        "date" => match (parse_date(a), parse_date(b)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => compare_natural(a, b),
+        },
+        "string" => a.cmp(b),
+        "case_insensitive" => a.to_lowercase().cmp(&b.to_lowercase()),
+        _ => compare_natural(a, b),
+    };
+}
+
+/// Compares strings `a` and `b` in natural order, meaning that runs of digits
+/// are compared by their numeric value, so that `file2` comes before `file10`.
+pub fn compare_natural(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut a_digits = String::new();
+                let mut b_digits = String::new();
+
+                while let Some(c) = a_chars.next_if(|c| c.is_ascii_digit()) {
+                    a_digits.push(c);
+                }
+
+                while let Some(c) = b_chars.next_if(|c| c.is_ascii_digit()) {
+                    b_digits.push(c);
+                }
+
+                let a_number = a_digits.trim_start_matches('0');
+                let b_number = b_digits.trim_start_matches('0');
+                let ordering = a_number
+                    .len()
+                    .cmp(&b_number.len())
+                    .then_with(|| a_number.cmp(b_number));
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase()).then(x.cmp(&y));
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+/// Parses a given `value` into a date-time. Supports RFC 3339 and RFC 2822
+/// date-times, as well as common date and date-time formats such as
+/// `2021-03-05`, `2021-03-05 14:30`, `05.03.2021` and `March 5, 2021`. Values
+/// without an offset are considered to be in the site's timezone.
+pub fn parse_date(value: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt);
+    }
+
+    if let Ok(dt) = DateTime::parse_from_rfc2822(value) {
+        return Some(dt);
+    }
+
+    let formats = [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%d",
+        "%Y/%m/%d",
+        "%d.%m.%Y",
+        "%B %d, %Y",
+        "%b %d, %Y",
+        "%d %B %Y",
+        "%d %b %Y",
+    ];
+
+    return formats
+        .iter()
+        .find_map(|format| parse_date_with_format(value, format));
+}
+
+/// Parses a given `value` into a date-time by a given `format`, such as
+/// `%d/%m/%Y`. The format may describe a date-time with or without an offset
+/// or only a date. Values without an offset are considered to be in the
+/// site's timezone.
+pub fn parse_date_with_format(value: &str, format: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+
+    if let Ok(dt) = DateTime::parse_from_str(value, format) {
+        return Some(dt);
+    }
+
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, format) {
+        return site_timezone().localize(&dt);
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, format) {
+        return site_timezone().localize(&date.and_hms_opt(0, 0, 0)?);
+    }
+
+    return None;
+}
+
+/// Formats a given date-time `dt` with a given strftime `format` in a given
+/// `timezone`, with month and day names in a given `locale`, such as `et_EE`.
+/// Returns an error for invalid formats and unknown locales rather than
+/// panicking.
+pub fn format_date(
+    dt: &DateTime<FixedOffset>,
+    format: &str,
+    timezone: Timezone,
+    locale: Option<&str>,
+) -> Result<String, String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(format!("invalid date format {}", format));
+    }
+
+    let locale = match locale {
+        Some(locale) => Some(parse_locale(locale).ok_or(format!("unknown locale {}", locale))?),
+        None => None,
+    };
+
+    return match timezone {
+        Timezone::Named(tz) => format_in(dt.with_timezone(&tz), format, locale),
+        Timezone::Fixed(offset) => format_in(dt.with_timezone(&offset), format, locale),
+    };
+}
+
+/// Formats a given date-time `dt` with a given strftime `format` and
+/// optional `locale`.
+fn format_in<T: TimeZone>(dt: DateTime<T>, format: &str, locale: Option<Locale>) -> Result<String, String>
+where
+    T::Offset: Display,
+{
+    let mut result = String::new();
+    let written = match locale {
+        Some(locale) => write!(result, "{}", dt.format_localized(format, locale)),
+        None => write!(result, "{}", dt.format(format)),
+    };
+
+    return written
+        .map(|_| result)
+        .map_err(|_| format!("invalid date format {}", format));
+}
+
+/// Parses a given `locale`, such as `et_EE`, `et-EE` or `et`. A bare
+/// language prefers the territory of the same name, such as `de_DE` for `de`,
+/// and otherwise is the first locale of that language, such as `et_EE` for
+/// `et`.
+#[cached(key = "String", convert = r#"{ locale.to_string() }"#)]
+fn parse_locale(locale: &str) -> Option<Locale> {
+    let locale = locale.replace('-', "_");
+
+    if let Ok(locale) = Locale::try_from(locale.as_str()) {
+        return Some(locale);
+    }
+
+    if locale.contains('_') {
+        return None;
+    }
+
+    let territories =
+        (b'A'..=b'Z').flat_map(|a| (b'A'..=b'Z').map(move |b| format!("{}{}", a as char, b as char)));
+
+    return std::iter::once(locale.to_uppercase())
+        .chain(territories)
+        .find_map(|territory| Locale::try_from(format!("{}_{}", locale, territory).as_str()).ok());
+}
+
+/// Describes the time between a given date-time `dt` and a given `now` in
+/// words, such as `3 days ago` or `in 2 hours`.
+pub fn relative_time(dt: &DateTime<FixedOffset>, now: &DateTime<Utc>) -> String {
+    let seconds = now.signed_duration_since(dt).num_seconds();
+    let elapsed = seconds.abs();
+
+    if elapsed < 45 {
+        return String::from("just now");
+    }
+
+    let units = [
+        (365 * 24 * 3600, "year"),
+        (30 * 24 * 3600, "month"),
+        (7 * 24 * 3600, "week"),
+        (24 * 3600, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+    ];
+    let (size, unit) = units
+        .iter()
+        .find(|(size, _)| elapsed >= *size)
+        .copied()
+        .unwrap_or((60, "minute"));
+    let count = (elapsed as f64 / size as f64).round().max(1.0) as i64;
+    let amount = format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" });
+
+    if seconds < 0 {
+        return format!("in {}", amount);
+    }
+
+    return format!("{} ago", amount);
+}
+
 /// Returns a value of a given `s` by a given `field`. Enables the retrieval
 /// of Struct values by key using a string.
 pub fn get_field_by_name<T, R>(s: T, field: &str) -> R
@@ -62,6 +433,7 @@ fn test_get_field_by_name() {
         meta: Default::default(),
         entry: "test-entry".to_string(),
         time_to_read: 0,
+        ..Default::default()
     };
 
     let path: String = get_field_by_name(item.clone(), "path");
@@ -78,4 +450,96 @@ fn test_get_field_by_name() {
 
     let field_that_doesnt_exist: String = get_field_by_name(item.clone(), "doesnt_exist");
     assert_eq!("".to_string(), field_that_doesnt_exist);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_sort_content_items() {
+    let _config = super::lock_config(super::test_config("/site"));
+    let item = |path: &str, order: &str, date: &str| ContentItem {
+        path: path.to_string(),
+        slug: path.to_string(),
+        meta: [("order", order), ("date", date)]
+            .iter()
+            .filter(|(_, v)| !v.is_empty())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        entry: String::new(),
+        time_to_read: 0,
+        ..Default::default()
+    };
+
+    let mut items = vec![
+        item("c", "10", "March 5, 2021"),
+        item("a", "2", "2021-03-05"),
+        item("b", "2", "2022-01-10"),
+        item("d", "", "2020-01-01"),
+    ];
+
+    let keys = vec![
+        SortKey {
+            by: "meta.order".to_string(),
+            order: "asc".to_string(),
+            compare: "auto".to_string(),
+            missing: "last".to_string(),
+        },
+        SortKey {
+            by: "meta.date".to_string(),
+            order: "desc".to_string(),
+            compare: "date".to_string(),
+            missing: "last".to_string(),
+        },
+    ];
+
+    sort_content_items(&mut items, &keys);
+    let paths: Vec<&str> = items.iter().map(|i| i.path.as_str()).collect();
+    assert_eq!(vec!["b", "a", "c", "d"], paths);
+}
+
+#[test]
+fn test_compare_values() {
+    let _config = super::lock_config(super::test_config("/site"));
+    assert_eq!(Ordering::Less, compare_values("2", "10", "auto"));
+    assert_eq!(Ordering::Greater, compare_values("2", "10", "string"));
+    assert_eq!(Ordering::Less, compare_values("05.03.2021", "2021-03-06", "auto"));
+    assert_eq!(Ordering::Less, compare_values("file2", "file10", "natural"));
+    assert_eq!(Ordering::Equal, compare_values("Oinky", "oinky", "case_insensitive"));
+
+    // Mixed values are all compared naturally, so that the order is total
+    let mut values = vec!["05.03.2021", "06.x", "31.12.2020", "2", "10"];
+    let compare = column_compare(&values, "auto");
+    assert_eq!("natural", compare);
+    values.sort_by(|a, b| compare_values(a, b, &compare));
+    assert_eq!(vec!["2", "05.03.2021", "06.x", "10", "31.12.2020"], values);
+    assert_eq!("date", column_compare(&["05.03.2021", "31.12.2020"], "auto"));
+    assert_eq!("numeric", column_compare(&["2", "10.5"], "auto"));
+    assert_eq!("natural", column_compare(&["2", "x"], "numeric"));
+}
+
+#[test]
+fn test_timezones_and_dates() {
+    let _config = super::lock_config(super::test_config("/site"));
+    let kolkata = Timezone::parse("Asia/Kolkata").unwrap();
+    let offset = Timezone::parse("+05:30").unwrap();
+    let dt = parse_date("2021-03-05T20:00:00Z").unwrap();
+
+    assert_eq!(Some(Timezone::Fixed(FixedOffset::east_opt(-3 * 3600).unwrap())), Timezone::parse("-3"));
+    assert_eq!(None, Timezone::parse("Mars/Olympus"));
+    assert_eq!("2021-03-06 01:30 IST", format_date(&dt, "%Y-%m-%d %H:%M %Z", kolkata, None).unwrap());
+    assert_eq!("2021-03-06 01:30", format_date(&dt, "%Y-%m-%d %H:%M", offset, None).unwrap());
+    assert_eq!("6. märts 2021", format_date(&dt, "%-d. %B %Y", kolkata, Some("et_EE")).unwrap());
+    assert_eq!("6. märts 2021", format_date(&dt, "%-d. %B %Y", kolkata, Some("et")).unwrap());
+    assert_eq!("6 March 2021", format_date(&dt, "%-d %B %Y", kolkata, Some("en")).unwrap());
+    assert_eq!("6. März 2021", format_date(&dt, "%-d. %B %Y", kolkata, Some("de")).unwrap());
+    assert!(format_date(&dt, "%Q", kolkata, None).is_err());
+    assert!(format_date(&dt, "%Y", kolkata, Some("xx")).is_err());
+    assert_eq!(
+        parse_date("2021-03-05"),
+        parse_date_with_format("05/03/2021", "%d/%m/%Y")
+    );
+    assert_eq!(None, parse_date("2021-13-01"));
+
+    let now = parse_date("2021-03-08T20:00:00Z").unwrap().with_timezone(&Utc);
+    assert_eq!("3 days ago", relative_time(&dt, &now));
+    assert_eq!("in 2 hours", relative_time(&parse_date("2021-03-08T22:00:00Z").unwrap(), &now));
+    assert_eq!("just now", relative_time(&parse_date("2021-03-08T20:00:10Z").unwrap(), &now));
+}
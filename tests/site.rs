@@ -0,0 +1,130 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A fresh temporary site directory, removed on drop, so also when an
+/// assertion fails.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("oinky-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        TempDir(dir)
+    }
+
+    /// Writes a file relative to the directory, creating its parents.
+    fn write(&self, relative: &str, contents: impl AsRef<[u8]>) {
+        let path = self.0.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+}
+
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn test_site_builder() {
+    let dir = TempDir::new("site");
+    dir.write("_layouts/post.hbs", "<h1>{{meta.title}}</h1>{{{entry}}}");
+    dir.write("blog/hello.md", "---\ntitle: Hello\nlayout: post\n---\nHi.");
+    dir.write("blog/draft.md", "---\ntitle: Draft\n---\nNo layout.");
+    dir.write("index.html.hbs", "{{#each content.posts}}{{meta.title}},{{/each}}");
+    dir.write("content.json", r#"[{"name": "posts", "from": "blog", "sort_by": "meta.title", "order": "asc"}]"#);
+
+    let site = oinky::Builder::new()
+        .source_dir(dir.display().to_string())
+        .output_dir("dist")
+        .jobs(2)
+        .build()
+        .unwrap();
+
+    assert_eq!(format!("{}/dist", dir.display()), site.output_dir());
+    assert_eq!(2, site.load_content().len());
+    assert!(site.compose_data().contains_key("posts"));
+    assert_eq!("<h1>Hello</h1><p>Hi.</p>\n", site.render_page("blog/hello.md").unwrap());
+    assert!(site.render_page("blog/draft.md").is_err());
+
+    let result = site.build();
+    assert!(result.is_ok(), "{:?}", result.errors);
+    assert_eq!(vec!["/blog/hello/index.html", "/index.html"], result.pages);
+    assert_eq!("Draft,Hello,", fs::read_to_string(dir.join("dist/index.html")).unwrap());
+    assert!(!dir.join("public").exists());
+
+    // Template errors fail the build instead of exiting
+    dir.write("broken.html.hbs", "{{#each}}");
+    let site = oinky::Builder::new().source_dir(dir.display().to_string()).build().unwrap();
+    assert!(!site.build().is_ok());
+
+    assert!(oinky::Builder::new().source_dir(dir.display().to_string()).timezone("Mars/Base").build().is_err());
+}
+
+#[test]
+fn test_concurrent_sites() {
+    let dirs: Vec<_> = (0..2).map(|i| TempDir::new(&format!("sites-{}", i))).collect();
+
+    for (i, dir) in dirs.iter().enumerate() {
+        dir.write("_layouts/post.hbs", "{{meta.title}}");
+        dir.write("index.html.hbs", format!("Site {}", i));
+
+        for j in 0..20 {
+            dir.write(&format!("blog/post{}.md", j), format!("---\ntitle: Site {}\nlayout: post\n---\n", i));
+        }
+    }
+
+    let handles: Vec<_> = dirs
+        .iter()
+        .map(|dir| {
+            let site = oinky::Builder::new().source_dir(dir.display().to_string()).build().unwrap();
+
+            std::thread::spawn(move || (0..3).map(|_| site.build().pages.len()).collect::<Vec<usize>>())
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(vec![21, 21, 21], handle.join().unwrap());
+    }
+
+    for (i, dir) in dirs.iter().enumerate() {
+        assert_eq!(format!("Site {}", i), fs::read_to_string(dir.join("public/index.html")).unwrap());
+        assert_eq!(
+            format!("Site {}", i),
+            fs::read_to_string(dir.join("public/blog/post7/index.html")).unwrap()
+        );
+    }
+
+    // An invalid oinky.toml is an error rather than exiting
+    let dir = TempDir::new("invalid-config");
+    dir.write("oinky.toml", "theme = [");
+    assert!(oinky::Builder::new().source_dir(dir.display().to_string()).build().is_err());
+}
+
+#[test]
+fn test_rebuild_reads_changes() {
+    let dir = TempDir::new("rebuild");
+    dir.write("_layouts/post.hbs", "v1 {{meta.title}}");
+    dir.write("blog/hello.md", "---\ntitle: Hello\nlayout: post\n---\n");
+
+    let site = oinky::Builder::new().source_dir(dir.display().to_string()).build().unwrap();
+    assert!(site.build().is_ok());
+    assert_eq!("v1 Hello", fs::read_to_string(dir.join("public/blog/hello/index.html")).unwrap());
+
+    // The same site picks up an edited layout and content on the next build
+    dir.write("_layouts/post.hbs", "v2 {{meta.title}}");
+    dir.write("blog/hello.md", "---\ntitle: Hi\nlayout: post\n---\n");
+    assert!(site.build().is_ok());
+    assert_eq!("v2 Hi", fs::read_to_string(dir.join("public/blog/hello/index.html")).unwrap());
+}